@@ -4,7 +4,6 @@ extern crate serde_json;
 extern crate tokio;
 
 use dkregistry::reference;
-use dkregistry::v2::manifest::Manifest;
 use std::result::Result;
 use std::str::FromStr;
 use std::{env, fs, io};
@@ -72,8 +71,7 @@ async fn run(
     let dclient = client.authenticate(&[&login_scope]).await?;
     let manifest = dclient.get_manifest(&image, &version).await?;
 
-    if let Manifest::S1Signed(s1s) = manifest {
-        let labels = s1s.get_labels(0);
+    if let Some(labels) = manifest.labels() {
         println!("got labels: {:#?}", labels);
     } else {
         println!("got no labels");
@@ -4,6 +4,7 @@ extern crate serde_json;
 extern crate tokio;
 
 use dkregistry::render;
+use dkregistry::v2::manifest::PlatformSpec;
 use futures::future::try_join_all;
 use std::path::Path;
 use std::result::Result;
@@ -66,7 +67,7 @@ async fn main() -> Result<(), boxed::Box<dyn error::Error>> {
         }
     };
 
-    let res = run(&registry, &image, &version, user, password, &path).await;
+    let res = run(&registry, &image, &version, user, password, path).await;
 
     if let Err(e) = res {
         println!("[{}] {}", registry, e);
@@ -99,14 +100,20 @@ async fn run(
     let login_scope = format!("repository:{}:pull", image);
 
     let dclient = client.authenticate(&[&login_scope]).await?;
-    let manifest = dclient.get_manifest(&image, &version).await?;
+    let platform = match env::var("DKREG_PLATFORM") {
+        Ok(p) => p.parse()?,
+        Err(_) => PlatformSpec::host(),
+    };
+    let manifest = dclient
+        .get_manifest_for_platform(image, version, &platform)
+        .await?;
     let layers_digests = manifest.layers_digests(None)?;
 
     println!("{} -> got {} layer(s)", &image, layers_digests.len(),);
 
     let blob_futures = layers_digests
         .iter()
-        .map(|layer_digest| dclient.get_blob(&image, &layer_digest))
+        .map(|layer_digest| dclient.get_blob(image, layer_digest))
         .collect::<Vec<_>>();
 
     let blobs = try_join_all(blob_futures).await?;
@@ -114,7 +121,7 @@ async fn run(
     println!("Downloaded {} layers", blobs.len());
 
     // TODO: use async io
-    std::fs::create_dir(&path).unwrap();
+    std::fs::create_dir(path).unwrap();
     let can_path = path.canonicalize().unwrap();
 
     println!("Unpacking layers to {:?}", &can_path);
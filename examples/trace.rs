@@ -64,7 +64,7 @@ async fn run(
 
     let login_scope = "";
 
-    let dclient = client.authenticate(&[&login_scope]).await?;
+    let dclient = client.authenticate(&[login_scope]).await?;
     let manifest = dclient.get_manifest(&image, &version).await?;
     println!("Size: {:?} ", manifest.download_size());
 
@@ -72,7 +72,7 @@ async fn run(
     println!("{} -> got {} layer(s)", &image, layers_digests.len(), );
 
     for layer_digest in &layers_digests {
-        let blob = dclient.get_blob_with_progress(&image, &layer_digest, None).await?;
+        let blob = dclient.get_blob_with_progress(&image, layer_digest, None).await?;
         println!("Layer {}, got {} bytes.\n", layer_digest, blob.len());
     }
 
@@ -0,0 +1,759 @@
+//! Registry credentials from the docker-client configuration.
+//!
+//! Credentials are looked up the way the Docker CLI does: a per-registry
+//! credential helper from `credHelpers` takes precedence, then the default
+//! credential store from `credsStore`, then entries stored in `auths`.
+//! Credential helpers are external programs named
+//! `docker-credential-<helper>`, see https://github.com/docker/docker-credential-helpers.
+//!
+//! The `auth.json` files of podman and other containers tools share this
+//! format, but their `auths` keys may also be scoped to a namespace or a
+//! repository, e.g. `quay.io/organization/repo`.
+
+use crate::errors::{Error, Result};
+use futures::future::{self, BoxFuture};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Username reported by credential helpers for identity tokens.
+const TOKEN_USERNAME: &str = "<token>";
+
+/// Credentials for a registry.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// Username and password, for Basic authentication or the OAuth2 password grant.
+    Basic { username: String, password: String },
+    /// Identity token, used as an OAuth2 refresh token.
+    IdentityToken(String),
+    /// Bearer token issued beforehand, sent to the registry as it is.
+    Bearer(String),
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Credentials::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"***")
+                .finish(),
+            Credentials::IdentityToken(_) => f.debug_tuple("IdentityToken").field(&"***").finish(),
+            Credentials::Bearer(_) => f.debug_tuple("Bearer").field(&"***").finish(),
+        }
+    }
+}
+
+/// Source of credentials, asked by a `Client` whenever it needs to authenticate.
+///
+/// This allows credentials to change during the lifetime of a client, for
+/// example when they are rotated.
+pub trait CredentialProvider: std::fmt::Debug + Send + Sync {
+    /// Get the credentials for `repository` on registry `registry`.
+    ///
+    /// The repository is `None` for requests not tied to a repository, such
+    /// as listing the catalog. Returning `None` makes the request anonymous.
+    fn credentials<'a>(
+        &'a self,
+        registry: &'a str,
+        repository: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Option<Credentials>>>;
+}
+
+/// Provider always returning the same credentials.
+#[derive(Debug, Clone)]
+pub struct StaticCredentials(Option<Credentials>);
+
+impl StaticCredentials {
+    pub fn new(credentials: Option<Credentials>) -> Self {
+        StaticCredentials(credentials)
+    }
+}
+
+impl CredentialProvider for StaticCredentials {
+    fn credentials<'a>(
+        &'a self,
+        _registry: &'a str,
+        _repository: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Option<Credentials>>> {
+        Box::pin(future::ok(self.0.clone()))
+    }
+}
+
+/// Provider reading credentials from environment variables.
+///
+/// Variables are read on every call, so they may be updated in between.
+/// An identity token or a bearer token takes precedence over a username
+/// and password.
+#[derive(Debug, Clone)]
+pub struct EnvCredentials {
+    username: String,
+    password: String,
+    identity_token: String,
+    token: String,
+}
+
+impl Default for EnvCredentials {
+    /// Read `REGISTRY_USERNAME`, `REGISTRY_PASSWORD`, `REGISTRY_IDENTITY_TOKEN`
+    /// and `REGISTRY_TOKEN`.
+    fn default() -> Self {
+        Self {
+            username: "REGISTRY_USERNAME".into(),
+            password: "REGISTRY_PASSWORD".into(),
+            identity_token: "REGISTRY_IDENTITY_TOKEN".into(),
+            token: "REGISTRY_TOKEN".into(),
+        }
+    }
+}
+
+impl EnvCredentials {
+    /// Set the variable holding the username.
+    pub fn username_var(mut self, name: &str) -> Self {
+        self.username = name.to_owned();
+        self
+    }
+
+    /// Set the variable holding the password.
+    pub fn password_var(mut self, name: &str) -> Self {
+        self.password = name.to_owned();
+        self
+    }
+
+    /// Set the variable holding the identity token.
+    pub fn identity_token_var(mut self, name: &str) -> Self {
+        self.identity_token = name.to_owned();
+        self
+    }
+
+    /// Set the variable holding the bearer token.
+    pub fn token_var(mut self, name: &str) -> Self {
+        self.token = name.to_owned();
+        self
+    }
+
+    fn read(&self) -> Option<Credentials> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        if let Some(token) = var(&self.token) {
+            return Some(Credentials::Bearer(token));
+        }
+        if let Some(token) = var(&self.identity_token) {
+            return Some(Credentials::IdentityToken(token));
+        }
+        match (var(&self.username), var(&self.password)) {
+            (None, None) => None,
+            (username, password) => Some(Credentials::Basic {
+                username: username.unwrap_or_default(),
+                password: password.unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+impl CredentialProvider for EnvCredentials {
+    fn credentials<'a>(
+        &'a self,
+        _registry: &'a str,
+        _repository: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Option<Credentials>>> {
+        Box::pin(future::ok(self.read()))
+    }
+}
+
+/// Provider reading credentials from docker-client and containers configuration files.
+///
+/// The files are read, and credential helpers run, on every call. As helpers
+/// may be slow, e.g. when refreshing a cloud token, the lookup runs on the
+/// blocking thread pool of the tokio runtime.
+#[derive(Debug, Clone, Default)]
+pub struct DockerConfigCredentials {
+    paths: Option<Vec<PathBuf>>,
+}
+
+impl DockerConfigCredentials {
+    /// Search the files returned by `auth_file_paths`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Search the given files, in order.
+    pub fn with_paths(paths: Vec<PathBuf>) -> Self {
+        DockerConfigCredentials { paths: Some(paths) }
+    }
+}
+
+impl CredentialProvider for DockerConfigCredentials {
+    fn credentials<'a>(
+        &'a self,
+        registry: &'a str,
+        repository: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Option<Credentials>>> {
+        let paths = self.paths.clone();
+        let registry = registry.to_owned();
+        let repository = repository.map(str::to_owned);
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || match paths {
+                Some(paths) => credentials_from_files(&paths, &registry, repository.as_deref()),
+                None => discover_credentials(&registry, repository.as_deref()),
+            })
+            .await
+            .map_err(std::io::Error::from)?
+        })
+    }
+}
+
+/// Docker-client configuration, typically stored under `~/.docker/config.json`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, AuthEntry>,
+    #[serde(rename = "credsStore", skip_serializing_if = "Option::is_none")]
+    creds_store: Option<String>,
+    #[serde(rename = "credHelpers", default)]
+    cred_helpers: HashMap<String, String>,
+    /// Directory to run credential helpers from, instead of looking them up in `PATH`.
+    #[serde(skip)]
+    helper_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct AuthEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    identitytoken: Option<String>,
+}
+
+/// Credentials as returned by `docker-credential-<helper> get`.
+#[derive(Debug, Deserialize)]
+struct HelperCredentials {
+    #[serde(rename = "Username")]
+    username: String,
+    #[serde(rename = "Secret")]
+    secret: String,
+}
+
+impl DockerConfig {
+    /// Parse the configuration from a JSON reader.
+    pub fn from_reader<T: Read>(reader: T) -> Result<Self> {
+        serde_json::from_reader(reader).map_err(Into::into)
+    }
+
+    /// Look up the credentials for registry `index`.
+    ///
+    /// `None` is returned if neither a credential helper nor the
+    /// configuration holds credentials for this registry.
+    pub fn credentials(&self, index: &str) -> Result<Option<Credentials>> {
+        self.repository_credentials(index, None)
+    }
+
+    /// Look up the credentials for `repository` on registry `index`.
+    ///
+    /// Entries scoped to the repository or one of its namespaces take
+    /// precedence, the longest matching prefix first, over those for the
+    /// whole registry.
+    pub fn repository_credentials(
+        &self,
+        index: &str,
+        repository: Option<&str>,
+    ) -> Result<Option<Credentials>> {
+        let server = server_address(index);
+        let host = hostname(server);
+
+        let helper = self.cred_helpers.get(host).or(self.creds_store.as_ref());
+        if let Some(helper) = helper {
+            if let Some(creds) = run_helper(self.helper_dir.as_deref(), helper, server)? {
+                trace!("Found credentials for {} with helper {}", index, helper);
+                return Ok(Some(creds));
+            }
+        }
+
+        for key in lookup_keys(index, repository) {
+            let entry = self
+                .auths
+                .iter()
+                .find(|(candidate, _)| normalize_key(candidate) == key);
+            if let Some((_, entry)) = entry {
+                trace!("Found credentials for {} under {}", index, key);
+                return entry.credentials();
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Paths of the files which may hold registry credentials, in lookup order.
+///
+/// These are `$REGISTRY_AUTH_FILE`, `$XDG_RUNTIME_DIR/containers/auth.json`,
+/// `$DOCKER_CONFIG/config.json` and `~/.docker/config.json`, for the
+/// variables which are set.
+pub fn auth_file_paths() -> Vec<PathBuf> {
+    auth_file_paths_from(|name| std::env::var_os(name))
+}
+
+fn auth_file_paths_from<F: Fn(&str) -> Option<OsString>>(var: F) -> Vec<PathBuf> {
+    let var = |name| {
+        var(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+
+    let mut paths = vec![];
+    if let Some(file) = var("REGISTRY_AUTH_FILE") {
+        paths.push(file);
+    }
+    if let Some(dir) = var("XDG_RUNTIME_DIR") {
+        paths.push(dir.join("containers").join("auth.json"));
+    }
+    if let Some(dir) = var("DOCKER_CONFIG") {
+        paths.push(dir.join("config.json"));
+    }
+    if let Some(home) = var("HOME").or_else(|| var("USERPROFILE")) {
+        paths.push(home.join(".docker").join("config.json"));
+    }
+    paths
+}
+
+/// Discover the credentials for `repository` on registry `index`.
+///
+/// The files returned by `auth_file_paths` are searched in order, and the
+/// first one holding credentials for the repository wins.
+pub fn discover_credentials(index: &str, repository: Option<&str>) -> Result<Option<Credentials>> {
+    credentials_from_files(&auth_file_paths(), index, repository)
+}
+
+fn credentials_from_files(
+    paths: &[PathBuf],
+    index: &str,
+    repository: Option<&str>,
+) -> Result<Option<Credentials>> {
+    for path in paths {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        trace!("Looking up credentials for {} in {}", index, path.display());
+        let config = DockerConfig::from_reader(BufReader::new(file))?;
+        if let Some(creds) = config.repository_credentials(index, repository)? {
+            return Ok(Some(creds));
+        }
+    }
+    Ok(None)
+}
+
+impl AuthEntry {
+    fn credentials(&self) -> Result<Option<Credentials>> {
+        if let Some(token) = self.identitytoken.as_ref().filter(|t| !t.is_empty()) {
+            return Ok(Some(Credentials::IdentityToken(token.clone())));
+        }
+
+        let (username, password) = match (&self.auth, &self.username, &self.password) {
+            (Some(auth), _, _) if !auth.is_empty() => {
+                let decoded = String::from_utf8(base64::decode(auth.as_str())?)?;
+                let creds: Vec<&str> = decoded.splitn(2, ':').collect();
+                match (creds.first(), creds.get(1)) {
+                    (Some(u), Some(p)) => (u.to_string(), p.to_string()),
+                    _ => (decoded.clone(), String::new()),
+                }
+            }
+            (_, Some(username), password) => {
+                (username.clone(), password.clone().unwrap_or_default())
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(Credentials::Basic { username, password }))
+    }
+}
+
+/// Server address under which credentials for `index` are stored.
+fn server_address(index: &str) -> &str {
+    match index {
+        // docker.io has some special casing in config.json
+        "docker.io" | "registry-1.docker.io" => "https://index.docker.io/v1/",
+        other => other,
+    }
+}
+
+/// Keys to look up in `auths`, the most specific first.
+fn lookup_keys(index: &str, repository: Option<&str>) -> Vec<String> {
+    let registry = match index {
+        "docker.io" | "registry-1.docker.io" | "index.docker.io" => "docker.io",
+        other => other,
+    };
+
+    let mut keys = vec![];
+    if let Some(repository) = repository {
+        let components = repository.split('/').collect::<Vec<_>>();
+        for i in (1..=components.len()).rev() {
+            keys.push(format!("{}/{}", registry, components[..i].join("/")));
+        }
+    }
+    keys.push(registry.to_string());
+    if registry == "docker.io" {
+        keys.push("index.docker.io".to_string());
+    }
+    keys
+}
+
+/// Normalize an `auths` key.
+///
+/// Keys with a scheme are Docker server addresses, which only name a
+/// registry. Other keys may be scoped to a namespace.
+fn normalize_key(key: &str) -> &str {
+    if key.starts_with("https://") || key.starts_with("http://") {
+        hostname(key)
+    } else {
+        key.trim_end_matches('/')
+    }
+}
+
+/// Strip the scheme and path from a server address, e.g. `https://quay.io/v1/`.
+fn hostname(server: &str) -> &str {
+    let server = server
+        .strip_prefix("https://")
+        .or_else(|| server.strip_prefix("http://"))
+        .unwrap_or(server);
+    server.split('/').next().unwrap_or(server)
+}
+
+/// Run `docker-credential-<helper> get` for `server`.
+///
+/// The helper is looked up in `dir` if given, in `PATH` otherwise.
+/// `None` is returned if the helper does not know the server.
+fn run_helper(dir: Option<&Path>, helper: &str, server: &str) -> Result<Option<Credentials>> {
+    let program = format!("docker-credential-{}", helper);
+    trace!("Running credential helper {} for {}", program, server);
+
+    let command = match dir {
+        Some(dir) => dir.join(&program),
+        None => PathBuf::from(&program),
+    };
+    let mut child = Command::new(command)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::CredentialHelper(program.clone(), e.to_string()))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(server.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim() == "credentials not found in native keychain" {
+            return Ok(None);
+        }
+        let message = match String::from_utf8_lossy(&output.stderr).trim() {
+            "" => stdout.trim().to_string(),
+            stderr => stderr.to_string(),
+        };
+        return Err(Error::CredentialHelper(program, message));
+    }
+
+    let creds: HelperCredentials = serde_json::from_slice(&output.stdout)?;
+    if creds.username == TOKEN_USERNAME {
+        Ok(Some(Credentials::IdentityToken(creds.secret)))
+    } else {
+        Ok(Some(Credentials::Basic {
+            username: creds.username,
+            password: creds.secret,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auths_entries() -> Result<()> {
+        let config = DockerConfig::from_reader(
+            r#"{"auths": {
+                "https://index.docker.io/v1/": {"auth": "dXNlcjpwYXNz"},
+                "quay.io": {"username": "robot", "password": "secret"},
+                "https://registry.example.com/v1/": {"auth": "", "identitytoken": "token"},
+                "empty.example.com": {}
+            }}"#
+            .as_bytes(),
+        )?;
+
+        assert_eq!(
+            config.credentials("registry-1.docker.io")?,
+            Some(Credentials::Basic {
+                username: "user".to_string(),
+                password: "pass".to_string()
+            })
+        );
+        assert_eq!(
+            config.credentials("quay.io")?,
+            Some(Credentials::Basic {
+                username: "robot".to_string(),
+                password: "secret".to_string()
+            })
+        );
+        assert_eq!(
+            config.credentials("registry.example.com")?,
+            Some(Credentials::IdentityToken("token".to_string()))
+        );
+        assert_eq!(config.credentials("empty.example.com")?, None);
+        assert_eq!(config.credentials("ghcr.io")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn namespaced_entries_match_longest_prefix() -> Result<()> {
+        let config = DockerConfig::from_reader(
+            r#"{"auths": {
+                "quay.io": {"auth": "cmVnaXN0cnk6cGFzcw=="},
+                "quay.io/org": {"auth": "b3JnOnBhc3M="},
+                "quay.io/org/repo": {"auth": "cmVwbzpwYXNz"},
+                "docker.io/library": {"auth": "bGlicmFyeTpwYXNz"}
+            }}"#
+            .as_bytes(),
+        )?;
+        let username = |index, repository| -> Result<Option<String>> {
+            Ok(match config.repository_credentials(index, repository)? {
+                Some(Credentials::Basic { username, .. }) => Some(username),
+                _ => None,
+            })
+        };
+
+        assert_eq!(
+            username("quay.io", Some("org/repo"))?.as_deref(),
+            Some("repo")
+        );
+        assert_eq!(
+            username("quay.io", Some("org/repository"))?.as_deref(),
+            Some("org")
+        );
+        assert_eq!(
+            username("quay.io", Some("org/repo/sub"))?.as_deref(),
+            Some("repo")
+        );
+        assert_eq!(
+            username("quay.io", Some("other/repo"))?.as_deref(),
+            Some("registry")
+        );
+        assert_eq!(username("quay.io", None)?.as_deref(), Some("registry"));
+        assert_eq!(
+            username("registry-1.docker.io", Some("library/busybox"))?.as_deref(),
+            Some("library")
+        );
+        assert_eq!(username("registry-1.docker.io", Some("user/image"))?, None);
+        Ok(())
+    }
+
+    /// Environment variables set by a test, removed once it ends.
+    struct EnvGuard(Vec<String>);
+
+    impl EnvGuard {
+        fn set(&mut self, name: String, value: &str) {
+            std::env::set_var(&name, value);
+            self.0.push(name);
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for name in &self.0 {
+                std::env::remove_var(name);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn env_credentials() -> Result<()> {
+        let prefix = format!("DKREGISTRY_TEST_ENV_CREDENTIALS_{}", std::process::id());
+        let var = |name: &str| format!("{}_{}", prefix, name);
+        let mut env = EnvGuard(vec![]);
+        let provider = EnvCredentials::default()
+            .username_var(&var("USERNAME"))
+            .password_var(&var("PASSWORD"))
+            .identity_token_var(&var("IDENTITY_TOKEN"))
+            .token_var(&var("TOKEN"));
+        let get = || provider.credentials("quay.io", Some("org/repo"));
+
+        assert_eq!(get().await?, None);
+
+        env.set(var("USERNAME"), "user");
+        env.set(var("PASSWORD"), "first");
+        assert_eq!(
+            get().await?,
+            Some(Credentials::Basic {
+                username: "user".to_string(),
+                password: "first".to_string()
+            })
+        );
+        // Rotated secrets are picked up.
+        env.set(var("PASSWORD"), "second");
+        assert_eq!(
+            get().await?,
+            Some(Credentials::Basic {
+                username: "user".to_string(),
+                password: "second".to_string()
+            })
+        );
+
+        env.set(var("TOKEN"), "token");
+        assert_eq!(get().await?, Some(Credentials::Bearer("token".to_string())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn auth_files_are_searched_in_order() -> Result<()> {
+        let env: HashMap<&str, &str> = vec![
+            ("REGISTRY_AUTH_FILE", "/etc/auth.json"),
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+            ("DOCKER_CONFIG", ""),
+            ("HOME", "/home/user"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            auth_file_paths_from(|name| env.get(name).map(OsString::from)),
+            vec![
+                PathBuf::from("/etc/auth.json"),
+                PathBuf::from("/run/user/1000/containers/auth.json"),
+                PathBuf::from("/home/user/.docker/config.json"),
+            ]
+        );
+
+        let dir =
+            std::env::temp_dir().join(format!("dkregistry-auth-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let podman = dir.join("auth.json");
+        let docker = dir.join("config.json");
+        std::fs::write(
+            &podman,
+            r#"{"auths": {"quay.io/org": {"auth": "cG9kbWFuOnBhc3M="}}}"#,
+        )?;
+        std::fs::write(
+            &docker,
+            r#"{"auths": {"quay.io": {"auth": "ZG9ja2VyOnBhc3M="}}}"#,
+        )?;
+        let paths = vec![dir.join("missing.json"), podman, docker];
+
+        let podman_creds = Credentials::Basic {
+            username: "podman".to_string(),
+            password: "pass".to_string(),
+        };
+        let docker_creds = Credentials::Basic {
+            username: "docker".to_string(),
+            password: "pass".to_string(),
+        };
+        assert_eq!(
+            credentials_from_files(&paths, "quay.io", Some("org/repo"))?,
+            Some(podman_creds)
+        );
+        assert_eq!(
+            credentials_from_files(&paths, "quay.io", Some("other/repo"))?,
+            Some(docker_creds)
+        );
+        assert_eq!(credentials_from_files(&paths, "ghcr.io", None)?, None);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn docker_config_provider() -> Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("dkregistry-config-provider-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let config = dir.join("config.json");
+        std::fs::write(
+            &config,
+            r#"{"auths": {"quay.io": {"auth": "dXNlcjpwYXNz"}}}"#,
+        )?;
+        let provider = DockerConfigCredentials::with_paths(vec![config]);
+
+        assert_eq!(
+            provider.credentials("quay.io", Some("org/repo")).await?,
+            Some(Credentials::Basic {
+                username: "user".to_string(),
+                password: "pass".to_string()
+            })
+        );
+        assert_eq!(provider.credentials("ghcr.io", None).await?, None);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn credential_helpers() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("dkregistry-helpers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let helper = dir.join("docker-credential-fake");
+        std::fs::write(
+            &helper,
+            r#"#!/bin/sh
+[ "$1" = get ] || exit 2
+server=$(cat)
+case "$server" in
+    helper.example.com) echo '{"ServerURL": "helper.example.com", "Username": "user", "Secret": "secret"}' ;;
+    https://index.docker.io/v1/) echo '{"ServerURL": "docker.io", "Username": "<token>", "Secret": "identity"}' ;;
+    broken.example.com) echo 'keychain locked' >&2; exit 1 ;;
+    *) echo 'credentials not found in native keychain'; exit 1 ;;
+esac
+"#,
+        )?;
+        std::fs::set_permissions(&helper, std::fs::Permissions::from_mode(0o755))?;
+
+        let mut config = DockerConfig::from_reader(
+            r#"{
+                "auths": {"store.example.com": {"auth": "dXNlcjpwYXNz"}},
+                "credsStore": "fake",
+                "credHelpers": {"missing.example.com": "missing"}
+            }"#
+            .as_bytes(),
+        )?;
+        config.helper_dir = Some(dir.clone());
+
+        assert_eq!(
+            config.credentials("helper.example.com")?,
+            Some(Credentials::Basic {
+                username: "user".to_string(),
+                password: "secret".to_string()
+            })
+        );
+        assert_eq!(
+            config.credentials("docker.io")?,
+            Some(Credentials::IdentityToken("identity".to_string()))
+        );
+        // Unknown to the store, found in the file instead.
+        assert_eq!(
+            config.credentials("store.example.com")?,
+            Some(Credentials::Basic {
+                username: "user".to_string(),
+                password: "pass".to_string()
+            })
+        );
+        assert_eq!(config.credentials("unknown.example.com")?, None);
+        assert!(matches!(
+            config.credentials("broken.example.com"),
+            Err(Error::CredentialHelper(_, message)) if message == "keychain locked"
+        ));
+        assert!(matches!(
+            config.credentials("missing.example.com"),
+            Err(Error::CredentialHelper(program, _)) if program == "docker-credential-missing"
+        ));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -37,7 +37,7 @@ pub enum Error {
     LoginReturnedBadToken,
     #[error("www-authenticate header parse error")]
     Www(#[from] crate::v2::WwwHeaderParseError),
-    #[error("request failed with status {status} and body of size {len}: {}", String::from_utf8_lossy(& body))]
+    #[error("request failed with status {status} and body of size {len}: {}", String::from_utf8_lossy(body))]
     Client {
         status: http::StatusCode,
         len: usize,
@@ -49,18 +49,50 @@ pub enum Error {
     MediaTypeSniff,
     #[error("manifest error")]
     Manifest(#[from] crate::v2::manifest::ManifestError),
+    #[error("platform is invalid")]
+    PlatformParse(#[from] crate::v2::manifest::PlatformParseError),
     #[error("reference is invalid")]
     ReferenceParse(#[from] crate::reference::ReferenceParseError),
     #[error("requested operation requires that credentials are available")]
     NoCredentials,
+    #[error("credential helper {0} failed: {1}")]
+    CredentialHelper(String, String),
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
     #[error("Download Failed")]
     DownloadFailed,
+    #[error("registry error {status}: {}", errors.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    Registry {
+        status: http::StatusCode,
+        errors: Vec<crate::v2::ApiError>,
+    },
+    #[error("unsupported URL scheme {0}")]
+    UnsupportedUrlScheme(String),
+    #[error("too many redirects, last target {0}")]
+    TooManyRedirects(String),
     #[error("Missing header {0}")]
     MissingHeader(String),
+    #[error("invalid header {0}: {1:?}")]
+    InvalidHeader(String, String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Errors reported by the registry, if this error carries any.
+    pub fn registry_errors(&self) -> &[crate::v2::ApiError] {
+        match self {
+            Error::Registry { errors, .. } => errors,
+            _ => &[],
+        }
+    }
+
+    /// Whether the registry reported an error with the given code.
+    pub fn has_error_code(&self, code: &crate::v2::ApiErrorCode) -> bool {
+        self.registry_errors().iter().any(|e| &e.code == code)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -6,6 +6,7 @@ use strum::EnumProperty;
 
 // For schema1 types, see https://docs.docker.com/registry/spec/manifest-v2-1/
 // For schema2 types, see https://docs.docker.com/registry/spec/manifest-v2-2/
+// For OCI types, see https://github.com/opencontainers/image-spec/blob/main/media-types.md
 
 #[derive(EnumProperty, EnumString, Display, Debug, Hash, PartialEq)]
 pub enum MediaTypes {
@@ -15,7 +16,6 @@ pub enum MediaTypes {
     ManifestV2S1,
     /// Signed manifest, version 2 schema 1.
     #[strum(
-        serialize = "application/vnd.docker.distribution.manifest.v1+prettyjws",
         to_string = "application/vnd.docker.distribution.manifest.v1+prettyjws",
 
         // TODO(steveeJ) find a generic way to handle this form
@@ -39,6 +39,50 @@ pub enum MediaTypes {
     #[strum(serialize = "application/vnd.docker.container.image.v1+json")]
     #[strum(props(Sub = "vnd.docker.container.image.v1+json"))]
     ContainerConfigV1,
+    /// OCI image manifest.
+    #[strum(serialize = "application/vnd.oci.image.manifest.v1+json")]
+    #[strum(props(Sub = "vnd.oci.image.manifest.v1+json"))]
+    OciImageManifest,
+    /// OCI image index.
+    #[strum(serialize = "application/vnd.oci.image.index.v1+json")]
+    #[strum(props(Sub = "vnd.oci.image.index.v1+json"))]
+    OciImageIndex,
+    /// OCI image configuration.
+    #[strum(serialize = "application/vnd.oci.image.config.v1+json")]
+    #[strum(props(Sub = "vnd.oci.image.config.v1+json"))]
+    OciImageConfig,
+    /// OCI image layer, as an uncompressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.v1.tar")]
+    #[strum(props(Sub = "vnd.oci.image.layer.v1.tar"))]
+    OciImageLayerTar,
+    /// OCI image layer, as a gzip-compressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.v1.tar+gzip")]
+    #[strum(props(Sub = "vnd.oci.image.layer.v1.tar+gzip"))]
+    OciImageLayerTgz,
+    /// OCI image layer, as a zstd-compressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.v1.tar+zstd")]
+    #[strum(props(Sub = "vnd.oci.image.layer.v1.tar+zstd"))]
+    OciImageLayerTzstd,
+    /// Foreign image layer, as a gzip-compressed tar, which is not pushed to registries.
+    #[strum(serialize = "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip")]
+    #[strum(props(Sub = "vnd.docker.image.rootfs.foreign.diff.tar.gzip"))]
+    ForeignImageLayerTgz,
+    /// OCI non-distributable image layer, as an uncompressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.nondistributable.v1.tar")]
+    #[strum(props(Sub = "vnd.oci.image.layer.nondistributable.v1.tar"))]
+    OciImageLayerNondistributableTar,
+    /// OCI non-distributable image layer, as a gzip-compressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.nondistributable.v1.tar+gzip")]
+    #[strum(props(Sub = "vnd.oci.image.layer.nondistributable.v1.tar+gzip"))]
+    OciImageLayerNondistributableTgz,
+    /// OCI non-distributable image layer, as a zstd-compressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.nondistributable.v1.tar+zstd")]
+    #[strum(props(Sub = "vnd.oci.image.layer.nondistributable.v1.tar+zstd"))]
+    OciImageLayerNondistributableTzstd,
+    /// OCI empty descriptor content, used by artifacts without configuration.
+    #[strum(serialize = "application/vnd.oci.empty.v1+json")]
+    #[strum(props(Sub = "vnd.oci.empty.v1+json"))]
+    OciEmpty,
     /// Generic JSON
     #[strum(serialize = "application/json")]
     #[strum(props(Sub = "json"))]
@@ -62,16 +106,39 @@ impl MediaTypes {
                     }
                     ("vnd.docker.image.rootfs.diff.tar.gzip", _) => Ok(MediaTypes::ImageLayerTgz),
                     ("vnd.docker.container.image.v1", "json") => Ok(MediaTypes::ContainerConfigV1),
-                    _ => return Err(crate::Error::UnknownMimeType(mtype.clone())),
+                    ("vnd.oci.image.manifest.v1", "json") => Ok(MediaTypes::OciImageManifest),
+                    ("vnd.oci.image.index.v1", "json") => Ok(MediaTypes::OciImageIndex),
+                    ("vnd.oci.image.config.v1", "json") => Ok(MediaTypes::OciImageConfig),
+                    ("vnd.oci.image.layer.v1.tar", "gzip") => Ok(MediaTypes::OciImageLayerTgz),
+                    ("vnd.oci.image.layer.v1.tar", "zstd") => Ok(MediaTypes::OciImageLayerTzstd),
+                    ("vnd.oci.image.layer.nondistributable.v1.tar", "gzip") => {
+                        Ok(MediaTypes::OciImageLayerNondistributableTgz)
+                    }
+                    ("vnd.oci.image.layer.nondistributable.v1.tar", "zstd") => {
+                        Ok(MediaTypes::OciImageLayerNondistributableTzstd)
+                    }
+                    ("vnd.oci.empty.v1", "json") => Ok(MediaTypes::OciEmpty),
+                    _ => Err(crate::Error::UnknownMimeType(mtype.clone())),
                 }
             }
-            _ => return Err(crate::Error::UnknownMimeType(mtype.clone())),
+            (mime::APPLICATION, subt, None) => match subt.to_string().as_str() {
+                "vnd.docker.image.rootfs.diff.tar.gzip" => Ok(MediaTypes::ImageLayerTgz),
+                "vnd.docker.image.rootfs.foreign.diff.tar.gzip" => {
+                    Ok(MediaTypes::ForeignImageLayerTgz)
+                }
+                "vnd.oci.image.layer.v1.tar" => Ok(MediaTypes::OciImageLayerTar),
+                "vnd.oci.image.layer.nondistributable.v1.tar" => {
+                    Ok(MediaTypes::OciImageLayerNondistributableTar)
+                }
+                _ => Err(crate::Error::UnknownMimeType(mtype.clone())),
+            },
+            _ => Err(crate::Error::UnknownMimeType(mtype.clone())),
         }
     }
     pub fn to_mime(&self) -> mime::Mime {
         match self {
-            &MediaTypes::ApplicationJson => Ok(mime::APPLICATION_JSON),
-            ref m => {
+            MediaTypes::ApplicationJson => Ok(mime::APPLICATION_JSON),
+            m => {
                 if let Some(s) = m.get_str("Sub") {
                     ("application/".to_string() + s).parse()
                 } else {
@@ -82,3 +149,27 @@ impl MediaTypes {
         .expect("to_mime should be always successful")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn layer_types_roundtrip_through_mime() -> Result<()> {
+        for mtype in &[
+            MediaTypes::ImageLayerTgz,
+            MediaTypes::ForeignImageLayerTgz,
+            MediaTypes::OciImageLayerTar,
+            MediaTypes::OciImageLayerTgz,
+            MediaTypes::OciImageLayerTzstd,
+            MediaTypes::OciImageLayerNondistributableTar,
+            MediaTypes::OciImageLayerNondistributableTgz,
+            MediaTypes::OciImageLayerNondistributableTzstd,
+        ] {
+            assert_eq!(mtype, &MediaTypes::from_mime(&mtype.to_mime())?);
+            assert_eq!(mtype, &MediaTypes::from_str(&mtype.to_string())?);
+        }
+        Ok(())
+    }
+}
@@ -103,6 +103,7 @@ impl fmt::Display for Version {
 /// A registry image reference.
 #[derive(Clone, Debug, Default)]
 pub struct Reference {
+    #[allow(dead_code)]
     has_schema: bool,
     raw_input: String,
     registry: String,
@@ -235,14 +236,14 @@ fn parse_url(input: &str) -> Result<Reference, ReferenceParseError> {
 
     // Handle images in default library namespace, that is:
     // `ubuntu` -> `library/ubuntu`
-    if components.is_empty() && &registry == DEFAULT_REGISTRY {
+    if components.is_empty() && registry == DEFAULT_REGISTRY {
         components.push_back("library".to_string());
     }
     components.push_back(image_name);
 
     // Check if all path components conform to the regex at
     // https://docs.docker.com/registry/spec/api/#overview.
-    const REGEX: &'static str = "^[a-z0-9]+(?:[._-][a-z0-9]+)*$";
+    const REGEX: &str = "^[a-z0-9]+(?:[._-][a-z0-9]+)*$";
     let path_re = regex::Regex::new(REGEX).expect("hardcoded regex is invalid");
     components.iter().try_for_each(|component| {
         if !path_re.is_match(component) {
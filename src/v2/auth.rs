@@ -1,6 +1,19 @@
+use crate::credentials::Credentials;
 use crate::errors::{Error, Result};
 use crate::v2::*;
-use reqwest::{header::HeaderValue, RequestBuilder, StatusCode, Url};
+use reqwest::{
+    header::{self, HeaderValue},
+    RequestBuilder, StatusCode, Url,
+};
+use std::collections::HashMap;
+use std::sync::MutexGuard;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Lifetime assumed for tokens which do not specify `expires_in`.
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Tokens are refreshed when they are this close to expiring.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(10);
 
 /// Represents all supported authentication schemes and is stored by `Client`.
 #[derive(Debug, Clone)]
@@ -21,8 +34,135 @@ impl Auth {
     }
 }
 
+/// Build the token scope for `actions` on repository `name`.
+///
+/// For example `repository_scope("foo/bar", &["pull", "push"])` results in
+/// `repository:foo/bar:pull,push`.
+pub fn repository_scope(name: &str, actions: &[&str]) -> String {
+    format!("repository:{}:{}", name, actions.join(","))
+}
+
+/// Build the token scopes needed to mount a blob from repository `from` into repository `name`.
+pub fn mount_scopes(name: &str, from: &str) -> Vec<String> {
+    vec![
+        repository_scope(name, &["pull", "push"]),
+        repository_scope(from, &["pull"]),
+    ]
+}
+
+/// Derive the token scopes needed for a request to the registry.
+///
+/// Pulling is enough for reads, deleting requires the `delete` action and
+/// any other method requires both `pull` and `push`. Blob mounts also need
+/// to pull from the source repository.
+pub(crate) fn request_scopes(method: &Method, url: &Url) -> Vec<String> {
+    lazy_static! {
+        static ref REPOSITORY_PATH: regex::Regex = regex::Regex::new(
+            r"^/v2/(?P<name>.+)/(manifests/[^/]+|blobs/uploads/[^/]*|blobs/[^/]+|tags/list)$",
+        )
+        .expect("this static regex is valid");
+    }
+
+    let path = url.path();
+    if path == "/v2/_catalog" {
+        return vec!["registry:catalog:*".to_string()];
+    }
+    let name = match REPOSITORY_PATH.captures(path).and_then(|c| c.name("name")) {
+        Some(name) => name.as_str(),
+        None => return vec![],
+    };
+
+    let actions: &[&str] = match *method {
+        Method::GET | Method::HEAD => &["pull"],
+        Method::DELETE => &["delete"],
+        _ => &["pull", "push"],
+    };
+    let mut scopes = vec![repository_scope(name, actions)];
+    if let Some((_, from)) = url.query_pairs().find(|(k, _)| k == "from") {
+        scopes.push(repository_scope(&from, &["pull"]));
+    }
+    scopes
+}
+
+/// Bearer tokens obtained by a `Client`, keyed by the scopes they were issued for.
+///
+/// The cache is shared between clones of a client.
+#[derive(Debug, Default)]
+pub(crate) struct TokenCache {
+    challenge: Option<WwwAuthenticateHeaderContentBearer>,
+    tokens: HashMap<String, CachedToken>,
+    /// Whether the registry asked for Basic authentication.
+    basic: bool,
+    /// Basic credentials, keyed by the repository they were resolved for.
+    basic_credentials: HashMap<Option<String>, Option<(String, String)>>,
+    /// OAuth2 refresh token, used to request tokens for further scopes.
+    refresh_token: Option<String>,
+}
+
+impl TokenCache {
+    pub(crate) fn new(refresh_token: Option<String>) -> Self {
+        TokenCache {
+            refresh_token,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CachedToken {
+    bearer_auth: BearerAuth,
+    expires_at: Instant,
+}
+
+impl CachedToken {
+    /// Expiry is measured from the time the token was received, as the
+    /// local clock cannot be trusted to agree with `issued_at`.
+    fn new(bearer_auth: BearerAuth) -> Self {
+        let lifetime = bearer_auth
+            .expires_in
+            .map(|secs| Duration::from_secs(secs.into()))
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+        CachedToken {
+            bearer_auth,
+            expires_at: Instant::now() + lifetime,
+        }
+    }
+
+    fn needs_refresh(&self) -> bool {
+        Instant::now() + TOKEN_REFRESH_MARGIN >= self.expires_at
+    }
+}
+
+/// Repository name of a `repository:<name>:<actions>` scope.
+fn scope_repository(scope: &str) -> Option<&str> {
+    scope
+        .strip_prefix("repository:")
+        .and_then(|scope| scope.rsplitn(2, ':').nth(1))
+}
+
+fn cache_key<S: AsRef<str>>(scopes: &[S]) -> String {
+    scopes
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Mask all but the first and last characters of a secret, for logging.
+pub(crate) fn mask(secret: &str) -> String {
+    let chars_count = secret.chars().count();
+    if chars_count <= 2 {
+        return "*".repeat(chars_count);
+    }
+    secret
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i == 0 || i == chars_count - 1 { c } else { '*' })
+        .collect()
+}
+
 /// Used for Bearer HTTP Authentication.
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Clone, Default, Deserialize, Serialize)]
 pub struct BearerAuth {
     token: String,
     expires_in: Option<u32>,
@@ -30,12 +170,224 @@ pub struct BearerAuth {
     refresh_token: Option<String>,
 }
 
+impl std::fmt::Debug for BearerAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("BearerAuth")
+            .field("token", &mask(&self.token))
+            .field("expires_in", &self.expires_in)
+            .field("issued_at", &self.issued_at)
+            .field("refresh_token", &self.refresh_token.as_deref().map(mask))
+            .finish()
+    }
+}
+
+impl BearerAuth {
+    /// Use a token obtained elsewhere.
+    pub fn new(token: String) -> Self {
+        BearerAuth {
+            token,
+            ..Default::default()
+        }
+    }
+
+    /// Set the lifetime of the token, in seconds.
+    pub fn with_expires_in(mut self, expires_in: Option<u32>) -> Self {
+        self.expires_in = expires_in;
+        self
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn expires_in(&self) -> Option<u32> {
+        self.expires_in
+    }
+
+    pub fn issued_at(&self) -> Option<&str> {
+        self.issued_at.as_deref()
+    }
+
+    pub fn refresh_token(&self) -> Option<&str> {
+        self.refresh_token.as_deref()
+    }
+}
+
+/// Claims of a registry token, as described by the Docker token specification.
+#[derive(Debug, Default, Deserialize)]
+struct TokenClaims {
+    #[serde(default)]
+    access: Vec<TokenAccess>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenAccess {
+    #[serde(rename = "type")]
+    resource_type: String,
+    name: String,
+    #[serde(default)]
+    actions: Vec<String>,
+}
+
+/// Check whether the `access` claims of a JWT token grant all `scopes`.
+///
+/// Tokens which are not JWTs cannot be checked, and are assumed not to
+/// grant anything.
+fn token_grants(token: &str, scopes: &[&str]) -> bool {
+    let claims = token
+        .split('.')
+        .nth(1)
+        .and_then(|payload| base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok())
+        .and_then(|payload| serde_json::from_slice::<TokenClaims>(&payload).ok());
+    let claims = match claims {
+        Some(claims) => claims,
+        None => return false,
+    };
+
+    scopes.iter().all(|scope| {
+        let mut parts = scope.splitn(2, ':');
+        let (resource_type, rest) = match (parts.next(), parts.next()) {
+            (Some(resource_type), Some(rest)) => (resource_type, rest),
+            _ => return false,
+        };
+        let mut parts = rest.rsplitn(2, ':');
+        let (actions, name) = match (parts.next(), parts.next()) {
+            (Some(actions), Some(name)) => (actions, name),
+            _ => return false,
+        };
+        actions.split(',').all(|action| {
+            claims.access.iter().any(|access| {
+                access.resource_type == resource_type
+                    && access.name == name
+                    && access.actions.iter().any(|a| a == action || a == "*")
+            })
+        })
+    })
+}
+
+/// OAuth2 client identifier sent to token endpoints.
+const OAUTH_CLIENT_ID: &str = "dkregistry";
+
+/// Response of a token endpoint.
+///
+/// The token is named `token` by the Docker token specification and
+/// `access_token` by OAuth2. Some endpoints return both.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+    expires_in: Option<u32>,
+    issued_at: Option<String>,
+    refresh_token: Option<String>,
+}
+
+impl TokenResponse {
+    fn into_bearer_auth(self) -> Result<BearerAuth> {
+        let token = self.token.or(self.access_token).unwrap_or_default();
+        match token.as_str() {
+            "unauthenticated" | "" => return Err(Error::InvalidAuthToken(token)),
+            _ => {}
+        };
+
+        trace!("authenticate: got token: {:?}", mask(&token));
+
+        Ok(BearerAuth {
+            token,
+            expires_in: self.expires_in,
+            issued_at: self.issued_at,
+            refresh_token: self.refresh_token,
+        })
+    }
+}
+
+/// Grant used to request a token with the OAuth2 flow.
+enum OAuthGrant<'a> {
+    Password(&'a str, &'a str),
+    RefreshToken(&'a str),
+}
+
 impl BearerAuth {
+    /// Fetch a token from the endpoint of a Bearer challenge.
+    ///
+    /// The OAuth2 flow is used when a refresh token is known, or when the
+    /// client forces it for credentials. Otherwise, and for endpoints which
+    /// do not support OAuth2, the token is fetched with a GET request using
+    /// Basic authentication. A refresh token returned by the endpoint is
+    /// kept for later token requests.
+    ///
+    /// Pre-issued bearer tokens are used as they are.
     async fn try_from_header_content(
         client: Client,
         scopes: &[&str],
-        credentials: Option<(String, String)>,
+        credentials: Option<Credentials>,
         bearer_header_content: WwwAuthenticateHeaderContentBearer,
+    ) -> Result<Self> {
+        let (credentials, identity_token) = match credentials {
+            Some(Credentials::Bearer(token)) => {
+                return Ok(BearerAuth {
+                    token,
+                    ..Default::default()
+                })
+            }
+            Some(Credentials::Basic { username, password }) => (Some((username, password)), None),
+            Some(Credentials::IdentityToken(token)) => (None, Some(token)),
+            None => (None, None),
+        };
+
+        let refresh_token = client
+            .token_cache()
+            .refresh_token
+            .clone()
+            .or(identity_token);
+
+        if client.anonymous_first && (credentials.is_some() || refresh_token.is_some()) {
+            match Self::try_from_token_endpoint(&client, scopes, None, &bearer_header_content)
+                .await
+            {
+                Ok(bearer_auth) if token_grants(&bearer_auth.token, scopes) => {
+                    trace!("authenticate: anonymous token grants {:?}", scopes);
+                    return Ok(bearer_auth);
+                }
+                Ok(_) => trace!("authenticate: anonymous token does not grant {:?}", scopes),
+                Err(e) => trace!("authenticate: anonymous token request failed: {}", e),
+            }
+        }
+
+        let grant = match (&refresh_token, &credentials) {
+            (Some(refresh_token), _) => Some(OAuthGrant::RefreshToken(refresh_token)),
+            (None, Some((user, password))) if client.force_oauth => {
+                Some(OAuthGrant::Password(user, password))
+            }
+            _ => None,
+        };
+
+        let oauth_auth = match grant {
+            Some(grant) => {
+                Self::try_from_oauth(&client, scopes, grant, &bearer_header_content).await?
+            }
+            None => None,
+        };
+        let bearer_auth = match oauth_auth {
+            Some(bearer_auth) => bearer_auth,
+            None => {
+                Self::try_from_token_endpoint(&client, scopes, credentials, &bearer_header_content)
+                    .await?
+            }
+        };
+
+        if let Some(refresh_token) = &bearer_auth.refresh_token {
+            client.token_cache().refresh_token = Some(refresh_token.clone());
+        }
+
+        Ok(bearer_auth)
+    }
+
+    /// Request a token with a GET request, as described by the Docker token specification.
+    async fn try_from_token_endpoint(
+        client: &Client,
+        scopes: &[&str],
+        credentials: Option<(String, String)>,
+        bearer_header_content: &WwwAuthenticateHeaderContentBearer,
     ) -> Result<Self> {
         let auth_ep = bearer_header_content.auth_ep(scopes);
         trace!("authenticate: token endpoint: {}", auth_ep);
@@ -50,7 +402,7 @@ impl BearerAuth {
                         password: Some(password),
                     })
                 }),
-                ..client
+                ..client.clone()
             }
         }
         .build_reqwest(Method::GET, url);
@@ -59,36 +411,125 @@ impl BearerAuth {
         let status = r.status();
         trace!("authenticate: got status {}", status);
         if status != StatusCode::OK {
-            return Err(Error::UnexpectedHttpStatus(status));
+            return Err(registry_error(r).await);
         }
 
-        let bearer_auth = r.json::<BearerAuth>().await?;
-
-        match bearer_auth.token.as_str() {
-            "unauthenticated" | "" => return Err(Error::InvalidAuthToken(bearer_auth.token)),
-            _ => {}
-        };
+        r.json::<TokenResponse>().await?.into_bearer_auth()
+    }
 
-        // mask the token before logging it
-        let chars_count = bearer_auth.token.chars().count();
-        let mask_start = std::cmp::min(1, chars_count - 1);
-        let mask_end = std::cmp::max(chars_count - 1, 1);
-        let mut masked_token = bearer_auth.token.clone();
-        masked_token.replace_range(mask_start..mask_end, &"*".repeat(mask_end - mask_start));
+    /// Request a token with a POST request, as described by the OAuth2 specification.
+    ///
+    /// `None` is returned if the endpoint does not support OAuth2.
+    async fn try_from_oauth(
+        client: &Client,
+        scopes: &[&str],
+        grant: OAuthGrant<'_>,
+        bearer_header_content: &WwwAuthenticateHeaderContentBearer,
+    ) -> Result<Option<Self>> {
+        let url = reqwest::Url::parse(&bearer_header_content.realm)?;
+        trace!("authenticate: OAuth2 token endpoint: {}", url);
 
-        trace!("authenticate: got token: {:?}", masked_token);
+        let scope = scopes.join(" ");
+        let mut form = vec![("client_id", OAUTH_CLIENT_ID), ("scope", &scope)];
+        if let Some(service) = &bearer_header_content.service {
+            form.push(("service", service));
+        }
+        match grant {
+            OAuthGrant::Password(user, password) => form.extend_from_slice(&[
+                ("grant_type", "password"),
+                ("username", user),
+                ("password", password),
+                // ask for a refresh token, to be used for later scopes
+                ("access_type", "offline"),
+            ]),
+            OAuthGrant::RefreshToken(refresh_token) => form.extend_from_slice(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ]),
+        };
 
-        Ok(bearer_auth)
+        let r = Client {
+            auth: None,
+            ..client.clone()
+        }
+        .build_reqwest(Method::POST, url)
+        .form(&form)
+        .send()
+        .await?;
+        let status = r.status();
+        trace!("authenticate: got status {}", status);
+        match status {
+            StatusCode::OK => r.json::<TokenResponse>().await?.into_bearer_auth().map(Some),
+            StatusCode::NOT_FOUND => {
+                trace!("authenticate: OAuth2 not supported, falling back to GET");
+                Ok(None)
+            }
+            _ => Err(registry_error(r).await),
+        }
     }
 }
 
 /// Used for Basic HTTP Authentication.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct BasicAuth {
     user: String,
     password: Option<String>,
 }
 
+impl std::fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("user", &self.user)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+impl BasicAuth {
+    pub fn new(user: String, password: Option<String>) -> Self {
+        BasicAuth { user, password }
+    }
+
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+}
+
+/// Authentication state of a `Client`, to be shared with other processes.
+///
+/// It is serializable, and holds secrets which should be stored accordingly.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AuthSession {
+    /// Endpoint issuing tokens, from the registry challenge.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub realm: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<Masked>,
+    pub tokens: Vec<SessionToken>,
+}
+
+/// Bearer token of an `AuthSession`, with the scopes it was issued for.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SessionToken {
+    pub scopes: Vec<String>,
+    pub token: Masked,
+    /// Expiry time, in seconds since the Unix epoch.
+    pub expires_at: u64,
+}
+
+/// Secret string which is masked in `Debug` output.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Masked(pub String);
+
+impl std::fmt::Debug for Masked {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", mask(&self.0))
+    }
+}
+
 /// Structured representation for the content of the authentication response header.
 #[derive(Debug, PartialEq, Eq, Deserialize)]
 pub(crate) enum WwwAuthenticateHeaderContent {
@@ -128,7 +569,7 @@ impl WwwAuthenticateHeaderContent {
         let captures = re.captures_iter(&header).collect::<Vec<_>>();
 
         let method = captures
-            .get(0)
+            .first()
             .ok_or(WwwHeaderParseError::InvalidValue)?
             .name("method")
             .ok_or(WwwHeaderParseError::FieldMethodMissing)?
@@ -182,7 +623,7 @@ impl WwwAuthenticateHeaderContent {
 }
 
 /// Structured content for the Bearer authentication response header.
-#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
 pub(crate) struct WwwAuthenticateHeaderContentBearer {
     realm: String,
     service: Option<String>,
@@ -190,6 +631,13 @@ pub(crate) struct WwwAuthenticateHeaderContentBearer {
 }
 
 impl WwwAuthenticateHeaderContentBearer {
+    /// The scopes requested by the challenge, if any.
+    fn scopes(&self) -> Option<Vec<String>> {
+        self.scope
+            .as_ref()
+            .map(|scope| scope.split(' ').map(str::to_string).collect())
+    }
+
     fn auth_ep(&self, scopes: &[&str]) -> String {
         let service = self
             .service
@@ -243,8 +691,11 @@ impl Client {
     /// Perform registry authentication and return the authenticated client.
     ///
     /// If Bearer authentication is used the returned client will be authorized for the requested scopes.
+    ///
+    /// Calling this is optional, as requests authenticate on demand when the
+    /// registry challenges them.
     pub async fn authenticate(mut self, scopes: &[&str]) -> Result<Self> {
-        let credentials = self.credentials.clone();
+        let credentials = self.resolve_credentials(scopes).await?;
 
         let client = Client {
             auth: None,
@@ -256,16 +707,22 @@ impl Client {
             authentication_header,
         )? {
             WwwAuthenticateHeaderContent::Basic(_) => {
-                let basic_auth = credentials
-                    .map(|(user, password)| BasicAuth {
-                        user,
+                let basic_auth = match credentials {
+                    Some(Credentials::Basic { username, password }) => BasicAuth {
+                        user: username,
                         password: Some(password),
-                    })
-                    .ok_or(Error::NoCredentials)?;
+                    },
+                    _ if self.anonymous_first => {
+                        trace!("authenticate: no credentials, continuing anonymously");
+                        return Ok(self);
+                    }
+                    _ => return Err(Error::NoCredentials),
+                };
 
                 Auth::Basic(basic_auth)
             }
             WwwAuthenticateHeaderContent::Bearer(bearer_header_content) => {
+                self.token_cache().challenge = Some(bearer_header_content.clone());
                 let bearer_auth = BearerAuth::try_from_header_content(
                     client,
                     scopes,
@@ -279,6 +736,17 @@ impl Client {
         };
 
         trace!("authenticate: login succeeded");
+        if let Auth::Bearer(bearer_auth) = &auth {
+            let mut cache = self.token_cache();
+            for scope in scopes {
+                cache
+                    .tokens
+                    .insert(scope.to_string(), CachedToken::new(bearer_auth.clone()));
+            }
+            cache
+                .tokens
+                .insert(cache_key(scopes), CachedToken::new(bearer_auth.clone()));
+        }
         self.auth = Some(auth);
 
         Ok(self)
@@ -303,11 +771,313 @@ impl Client {
         match status {
             reqwest::StatusCode::OK => Ok(true),
             reqwest::StatusCode::UNAUTHORIZED => Ok(false),
-            _ => Err(Error::UnexpectedHttpStatus(status)),
+            _ => Err(registry_error(resp).await),
+        }
+    }
+}
+
+impl Client {
+    /// Send a request, authenticating on demand.
+    ///
+    /// Registry requests use the cached token for the scopes they need,
+    /// which is refreshed shortly before it expires. If the registry answers
+    /// with an authentication challenge, a token is fetched for the scope it
+    /// asks for, or Basic credentials are used, and the request is replayed
+    /// once. This lets clients which never called `authenticate` access
+    /// private repositories.
+    ///
+    /// Pulls are tried on the registry mirrors first, if any.
+    pub(crate) async fn send_request(&self, builder: RequestBuilder) -> Result<reqwest::Response> {
+        self.send_request_with(builder, false).await
+    }
+
+    /// Like `send_request`, for requests built by `build_blob_reqwest`.
+    pub(crate) async fn send_blob_reqwest(&self, builder: RequestBuilder) -> Result<reqwest::Response> {
+        self.send_request_with(builder, true).await
+    }
+
+    async fn send_request_with(
+        &self,
+        builder: RequestBuilder,
+        no_redirects: bool,
+    ) -> Result<reqwest::Response> {
+        let (client, request) = builder.build_split();
+        let mut request = request?;
+
+        if let Some(res) = self.send_to_mirrors(&request, no_redirects).await? {
+            return Ok(res);
+        }
+
+        let registry_origin = Url::parse(&self.base_url)?.origin();
+        let managed = request.url().origin() == registry_origin
+            && match self.auth {
+                None => true,
+                Some(Auth::Bearer(_)) => request.headers().contains_key(header::AUTHORIZATION),
+                Some(Auth::Basic(_)) => false,
+            };
+        if !managed {
+            return client.execute(request).await.map_err(Into::into);
+        }
+
+        let scopes = request_scopes(request.method(), request.url());
+        if let Some(token) = self.cached_token(&scopes).await? {
+            set_bearer_token(&mut request, &token)?;
+        } else if self.token_cache().basic {
+            if let Some(credentials) = self.basic_credentials(&scopes, false).await? {
+                set_basic_credentials(&mut request, &credentials)?;
+            }
+        }
+
+        let retry = request.try_clone();
+        let res = client.execute(request).await?;
+        if res.status() != StatusCode::UNAUTHORIZED {
+            return Ok(res);
+        }
+
+        let challenge = res
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .cloned()
+            .map(WwwAuthenticateHeaderContent::from_www_authentication_header);
+        let (mut retry, challenge) = match (retry, challenge) {
+            (Some(retry), Some(Ok(challenge))) => (retry, challenge),
+            _ => return Ok(res),
+        };
+
+        match challenge {
+            WwwAuthenticateHeaderContent::Bearer(challenge) => {
+                let challenge_scopes = challenge.scopes();
+                let token_scopes = challenge_scopes.as_ref().unwrap_or(&scopes);
+                trace!(
+                    "{} {} unauthorized, fetching a token for {:?}",
+                    retry.method(),
+                    retry.url(),
+                    token_scopes
+                );
+                let token = self.fetch_token(challenge.clone(), token_scopes).await?;
+                if token_scopes != &scopes {
+                    // Later requests look the token up by their own scopes.
+                    let mut cache = self.token_cache();
+                    if let Some(cached) = cache.tokens.get(&cache_key(token_scopes)).cloned() {
+                        cache.tokens.insert(cache_key(&scopes), cached);
+                    }
+                }
+                set_bearer_token(&mut retry, &token)?;
+            }
+            WwwAuthenticateHeaderContent::Basic(_) => {
+                let credentials = match &self.auth {
+                    None => self.basic_credentials(&scopes, true).await?,
+                    Some(_) => None,
+                };
+                let credentials = match credentials {
+                    Some(credentials) => credentials,
+                    None => return Ok(res),
+                };
+                trace!(
+                    "{} {} unauthorized, using basic credentials",
+                    retry.method(),
+                    retry.url()
+                );
+                self.token_cache().basic = true;
+                set_basic_credentials(&mut retry, &credentials)?;
+            }
+        }
+
+        client.execute(retry).await.map_err(Into::into)
+    }
+
+    /// Look up the token for `scopes`, refreshing it if it is about to expire.
+    async fn cached_token(&self, scopes: &[String]) -> Result<Option<String>> {
+        let (cached, challenge) = {
+            let cache = self.token_cache();
+            let cached = cache
+                .tokens
+                .get(&cache_key(scopes))
+                .map(|t| (t.bearer_auth.token.clone(), t.needs_refresh()));
+            (cached, cache.challenge.clone())
+        };
+
+        match (cached, challenge) {
+            (Some((_, true)), Some(challenge)) => {
+                trace!("Refreshing token for {:?}", scopes);
+                self.fetch_token(challenge, scopes).await.map(Some)
+            }
+            (cached, _) => Ok(cached.map(|(token, _)| token)),
+        }
+    }
+
+    /// Fetch a token for `scopes` from the endpoint of `challenge` and cache it.
+    async fn fetch_token(
+        &self,
+        challenge: WwwAuthenticateHeaderContentBearer,
+        scopes: &[String],
+    ) -> Result<String> {
+        let client = Client {
+            auth: None,
+            ..self.clone()
+        };
+        let scope_refs = scopes.iter().map(String::as_str).collect::<Vec<_>>();
+        let credentials = self.resolve_credentials(&scope_refs).await?;
+        let bearer_auth = BearerAuth::try_from_header_content(
+            client,
+            &scope_refs,
+            credentials,
+            challenge.clone(),
+        )
+        .await?;
+
+        let token = bearer_auth.token.clone();
+        let mut cache = self.token_cache();
+        cache.challenge = Some(challenge);
+        cache
+            .tokens
+            .insert(cache_key(scopes), CachedToken::new(bearer_auth));
+        Ok(token)
+    }
+
+    /// Export the authentication state, to be imported by another client.
+    ///
+    /// Tokens which already expired are left out.
+    pub fn export_auth(&self) -> AuthSession {
+        let cache = self.token_cache();
+        let now = Instant::now();
+        let tokens = cache
+            .tokens
+            .iter()
+            .filter(|(_, cached)| cached.expires_at > now)
+            .map(|(key, cached)| SessionToken {
+                scopes: key
+                    .split(' ')
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                token: Masked(cached.bearer_auth.token.clone()),
+                expires_at: (SystemTime::now() + (cached.expires_at - now))
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        AuthSession {
+            realm: cache.challenge.as_ref().map(|c| c.realm.clone()),
+            service: cache.challenge.as_ref().and_then(|c| c.service.clone()),
+            refresh_token: cache.refresh_token.clone().map(Masked),
+            tokens,
+        }
+    }
+
+    /// Import an authentication state exported by `export_auth`.
+    ///
+    /// Imported tokens are used for requests needing their scopes, and
+    /// shared with clones of this client.
+    pub fn import_auth(&self, session: AuthSession) {
+        let mut cache = self.token_cache();
+        if let Some(realm) = session.realm {
+            cache.challenge = Some(WwwAuthenticateHeaderContentBearer {
+                realm,
+                service: session.service,
+                scope: None,
+            });
+        }
+        if let Some(Masked(refresh_token)) = session.refresh_token {
+            cache.refresh_token = Some(refresh_token);
+        }
+
+        let now = SystemTime::now();
+        for token in session.tokens {
+            let expires_at = UNIX_EPOCH + Duration::from_secs(token.expires_at);
+            let lifetime = match expires_at.duration_since(now) {
+                Ok(lifetime) => lifetime,
+                Err(_) => continue,
+            };
+            cache.tokens.insert(
+                cache_key(&token.scopes),
+                CachedToken {
+                    bearer_auth: BearerAuth::new(token.token.0),
+                    expires_at: Instant::now() + lifetime,
+                },
+            );
+        }
+    }
+
+    /// Get the credentials for a request needing `scopes`.
+    ///
+    /// A configured credential provider is asked for the repository of the
+    /// scopes, otherwise the configured username and password are used.
+    async fn resolve_credentials<S: AsRef<str>>(&self, scopes: &[S]) -> Result<Option<Credentials>> {
+        let provider = match &self.credential_provider {
+            Some(provider) => provider,
+            None => {
+                return Ok(self
+                    .credentials
+                    .clone()
+                    .map(|(username, password)| Credentials::Basic { username, password }))
+            }
+        };
+
+        let repository = scopes
+            .iter()
+            .find_map(|scope| scope_repository(scope.as_ref()));
+        trace!(
+            "Asking credential provider for {} repository {:?}",
+            self.index,
+            repository
+        );
+        provider.credentials(&self.index, repository).await
+    }
+
+    /// Get the username and password for a request needing `scopes`, if any.
+    ///
+    /// They are cached per repository, so that a credential provider is not
+    /// asked on every request; `refresh` resolves them again, e.g. after
+    /// the registry rejected them.
+    async fn basic_credentials(&self, scopes: &[String], refresh: bool) -> Result<Option<(String, String)>> {
+        let repository = scopes
+            .iter()
+            .find_map(|scope| scope_repository(scope))
+            .map(str::to_owned);
+        if !refresh {
+            if let Some(cached) = self.token_cache().basic_credentials.get(&repository) {
+                return Ok(cached.clone());
+            }
         }
+
+        let credentials = match self.resolve_credentials(scopes).await? {
+            Some(Credentials::Basic { username, password }) => Some((username, password)),
+            _ => None,
+        };
+        self.token_cache()
+            .basic_credentials
+            .insert(repository, credentials.clone());
+        Ok(credentials)
+    }
+
+    fn token_cache(&self) -> MutexGuard<'_, TokenCache> {
+        // The cache is never left in an inconsistent state, so poisoning is harmless.
+        self.tokens
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
     }
 }
 
+fn set_bearer_token(request: &mut reqwest::Request, token: &str) -> Result<()> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+        .map_err(|_| Error::InvalidAuthToken(token.to_string()))?;
+    value.set_sensitive(true);
+    request.headers_mut().insert(header::AUTHORIZATION, value);
+    Ok(())
+}
+
+fn set_basic_credentials(request: &mut reqwest::Request, (user, password): &(String, String)) -> Result<()> {
+    let encoded = base64::encode(format!("{}:{}", user, password));
+    let mut value = HeaderValue::from_str(&format!("Basic {}", encoded))
+        .map_err(|_| Error::InvalidAuthToken("basic credentials".to_string()))?;
+    value.set_sensitive(true);
+    request.headers_mut().insert(header::AUTHORIZATION, value);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -364,6 +1134,87 @@ mod tests {
         Ok(())
     }
 
+    #[test_case("GET", "/v2/library/busybox/manifests/latest", &["repository:library/busybox:pull"]; "pull manifest")]
+    #[test_case("HEAD", "/v2/foo/blobs/sha256:abcd", &["repository:foo:pull"]; "check blob")]
+    #[test_case("DELETE", "/v2/foo/manifests/sha256:abcd", &["repository:foo:delete"]; "delete manifest")]
+    #[test_case("PUT", "/v2/foo/bar/manifests/blobs", &["repository:foo/bar:pull,push"]; "push manifest tagged like an endpoint")]
+    #[test_case("PATCH", "/v2/foo/blobs/uploads/1234", &["repository:foo:pull,push"]; "upload chunk")]
+    #[test_case("POST", "/v2/foo/blobs/uploads/?mount=sha256:abcd&from=bar", &["repository:foo:pull,push", "repository:bar:pull"]; "mount blob")]
+    #[test_case("GET", "/v2/foo/tags/list?n=10", &["repository:foo:pull"]; "list tags")]
+    #[test_case("GET", "/v2/_catalog?n=10", &["registry:catalog:*"]; "catalog")]
+    #[test_case("GET", "/v2/", &[]; "version check")]
+    fn request_scopes_from_url(method: &str, path: &str, expected: &[&str]) {
+        let method = Method::from_bytes(method.as_bytes()).unwrap();
+        let url = Url::parse(&format!("https://registry.example.com{}", path)).unwrap();
+
+        assert_eq!(request_scopes(&method, &url), expected);
+    }
+
+    fn jwt(claims: &str) -> String {
+        format!(
+            "{}.{}.signature",
+            base64::encode_config(r#"{"alg":"ES256","typ":"JWT"}"#, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(claims, base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    #[test]
+    fn token_grants_checks_access_claims() {
+        let token = jwt(
+            r#"{"iss": "auth", "access": [
+                {"type": "repository", "name": "library/busybox", "actions": ["pull"]},
+                {"type": "repository", "name": "org/app", "actions": ["*"]}
+            ]}"#,
+        );
+
+        assert!(token_grants(&token, &["repository:library/busybox:pull"]));
+        assert!(token_grants(&token, &["repository:org/app:pull,push"]));
+        assert!(!token_grants(&token, &["repository:library/busybox:pull,push"]));
+        assert!(!token_grants(
+            &token,
+            &["repository:library/busybox:pull", "repository:other:pull"]
+        ));
+        assert!(!token_grants(&jwt(r#"{"access": []}"#), &["repository:library/busybox:pull"]));
+        assert!(!token_grants("opaque", &["repository:library/busybox:pull"]));
+    }
+
+    #[test]
+    fn secrets_are_masked_in_debug_output() {
+        assert_eq!(mask("secret-token"), "s**********n");
+        assert_eq!(mask("ab"), "**");
+
+        let bearer_auth = BearerAuth::new("secret-token".to_string());
+        let basic_auth = BasicAuth::new("user".to_string(), Some("hunter2".to_string()));
+        let session = AuthSession {
+            tokens: vec![SessionToken {
+                scopes: vec!["repository:foo:pull".to_string()],
+                token: Masked("secret-token".to_string()),
+                expires_at: 0,
+            }],
+            ..Default::default()
+        };
+
+        for debug in &[
+            format!("{:?}", Auth::Bearer(bearer_auth)),
+            format!("{:?}", Auth::Basic(basic_auth)),
+            format!("{:?}", session),
+        ] {
+            assert!(!debug.contains("secret-token"), "{}", debug);
+            assert!(!debug.contains("hunter2"), "{}", debug);
+        }
+    }
+
+    #[test]
+    fn mount_scopes_cover_source_and_target() {
+        assert_eq!(
+            mount_scopes("library/target", "library/source"),
+            vec![
+                "repository:library/target:pull,push".to_string(),
+                "repository:library/source:pull".to_string(),
+            ]
+        );
+    }
+
     // The following test checks the url construction within the 'auth_ep'
     // method of WwwAuthenticateHeaderContentBearer.
     // Tests that the result is correctly parsed by Url::parse and that the
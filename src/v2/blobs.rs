@@ -1,11 +1,15 @@
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::path::{Path, PathBuf};
-use std::io::Write;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
 use std::sync::mpsc::Sender;
 use crate::errors::{Error, Result};
+use crate::v2::content_digest::ContentHasher;
 use crate::v2::*;
+use async_stream::try_stream;
+use bytes::Bytes;
 use reqwest;
-use reqwest::{Method, StatusCode};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{Method, StatusCode, Url};
 
 impl Client {
     /// Check if a blob exists.
@@ -15,199 +19,564 @@ impl Client {
             reqwest::Url::parse(&ep)?
         };
 
-        let res = self.build_reqwest(Method::HEAD, url.clone()).send().await?;
+        let res = self.send_request(self.build_reqwest(Method::HEAD, url.clone())).await?;
 
         trace!("Blob HEAD status: {:?}", res.status());
 
         match res.status() {
             StatusCode::OK => Ok(true),
-            _ => Ok(false),
+            StatusCode::NOT_FOUND => Ok(false),
+            _ => Err(registry_error(res).await),
         }
     }
 
-    /// Retrieve blob.
-    pub async fn get_blob(&self, name: &str, digest: &str) -> Result<Vec<u8>> {
+    /// Delete a blob.
+    ///
+    /// Blobs can only be deleted by digest.
+    pub async fn delete_blob(&self, name: &str, digest: &str) -> Result<()> {
         let digest = ContentDigest::try_new(digest.to_string())?;
 
-        let blob = {
+        let url = {
             let ep = format!("{}/v2/{}/blobs/{}", self.base_url, name, digest);
-            let url = reqwest::Url::parse(&ep)?;
+            reqwest::Url::parse(&ep)?
+        };
+
+        let res = self.send_request(self.build_reqwest(Method::DELETE, url)).await?;
 
-            let res = self.build_reqwest(Method::GET, url.clone()).send().await?;
+        trace!("DELETE {} status: {}", res.url(), res.status());
 
-            trace!("GET {} status: {}", res.url(), res.status());
+        match res.status() {
+            StatusCode::ACCEPTED => Ok(()),
+            _ => Err(registry_error(res).await),
+        }
+    }
+
+    /// Retrieve blob.
+    pub async fn get_blob(&self, name: &str, digest: &str) -> Result<Vec<u8>> {
+        let digest = ContentDigest::try_new(digest.to_string())?;
+
+        let blob = {
+            let res = self.get_blob_response(name, &digest, 0).await?;
             let status = res.status();
 
-            // Let client errors through to populate them with the body
-            if !(status.is_success() || status.is_client_error()) {
-                return Err(Error::UnexpectedHttpStatus(status));
+            if !status.is_success() {
+                return Err(registry_error(res).await);
             }
 
-            let status = res.status();
             let body_vec = res.bytes().await?.to_vec();
-            let len = body_vec.len();
-
-            if status.is_success() {
-                trace!("Successfully received blob with {} bytes ", len);
-                Ok(body_vec)
-            } else if status.is_client_error() {
-                Err(Error::Client {
-                    status,
-                    len,
-                    body: body_vec,
-                })
-            } else {
-                // We only want to handle success and client errors here
-                error!(
-                    "Received unexpected HTTP status '{}' after fetching the body. Please submit a bug report.",
-                    status
-                );
-                Err(Error::UnexpectedHttpStatus(status))
+            trace!("Successfully received blob with {} bytes ", body_vec.len());
+            body_vec
+        };
+
+        digest.try_verify(&blob)?;
+        Ok(blob)
+    }
+
+    /// Retrieve the blob for a layer descriptor.
+    ///
+    /// Foreign and non-distributable layers, e.g. Windows base layers, are
+    /// usually not stored in the registry but at the locations listed in
+    /// the descriptor `urls`. Each of them is tried in order, without
+    /// registry credentials, and the first one whose content matches the
+    /// digest is returned. If none succeeds, the blob is fetched from the
+    /// registry.
+    pub async fn get_layer(&self, name: &str, layer: &manifest::Descriptor) -> Result<Vec<u8>> {
+        let digest = ContentDigest::try_new(layer.digest.clone())?;
+
+        for url in layer.urls.iter().flatten() {
+            match self.get_blob_from_url(&digest, url).await {
+                Ok(blob) => return Ok(blob),
+                Err(e) => debug!("Unable to fetch {} from {}, trying next location: {}", digest, url, e),
             }
-        }?;
+        }
+
+        self.get_blob(name, &layer.digest).await
+    }
+
+    async fn get_blob_from_url(&self, digest: &ContentDigest, url: &str) -> Result<Vec<u8>> {
+        let url = Url::parse(url)?;
+        match url.scheme() {
+            "http" | "https" => {}
+            scheme => return Err(Error::UnsupportedUrlScheme(scheme.to_string())),
+        }
 
+        // Foreign locations are not registries, their error bodies carry no meaning.
+        let res = self.send_blob_request(Method::GET, url, HeaderMap::new()).await?;
+        if !res.status().is_success() {
+            return Err(Error::UnexpectedHttpStatus(res.status()));
+        }
+
+        let blob = res.bytes().await?.to_vec();
         digest.try_verify(&blob)?;
-        Ok(blob.to_vec())
+        Ok(blob)
     }
 
-    /// Retrieve blob with progress
-    pub async fn get_blob_with_progress(&self, name: &str, digest: &str, sender: Option<Sender<u64>>) -> Result<Vec<u8>> {
+    /// Retrieve blob as a stream of chunks.
+    ///
+    /// The content is verified against the digest while it is streamed,
+    /// so that the blob never has to be held in memory. If the digest does
+    /// not match, the stream yields an error after the last chunk; consumers
+    /// must not trust the content before the stream has ended successfully.
+    ///
+    /// The stream can be turned into an `AsyncRead` e.g. with
+    /// `tokio_util::io::StreamReader`.
+    pub async fn get_blob_stream(
+        &self,
+        name: &str,
+        digest: &str,
+    ) -> Result<impl Stream<Item = Result<Bytes>>> {
         let digest = ContentDigest::try_new(digest.to_string())?;
 
-        let blob = {
-            let ep = format!("{}/v2/{}/blobs/{}", self.base_url, name, digest);
-            let url = reqwest::Url::parse(&ep)?;
+        let res = self.get_blob_response(name, &digest, 0).await?;
+        if !res.status().is_success() {
+            return Err(registry_error(res).await);
+        }
 
-            let res = self.build_reqwest(Method::GET, url.clone()).send().await?;
+        Ok(verified_stream(res, digest.hasher()))
+    }
 
-            trace!("GET {} status: {}", res.url(), res.status());
-            let status = res.status();
-            // Let client errors through to populate them with the body
-            if !(status.is_success() || status.is_client_error()) {
-                if let Some(send) = sender {
-                    drop(send);
-                };
-                return Err(Error::UnexpectedHttpStatus(status));
+    /// Retrieve blob as a stream of chunks, fetching byte ranges in parallel.
+    ///
+    /// The blob size and range support are discovered with a HEAD request.
+    /// The blob is then split into ranges of `chunk_size` bytes, up to
+    /// `parallelism` of which are fetched concurrently. Ranges are yielded
+    /// in order and the digest is verified at the end, as in `get_blob_stream`.
+    /// At most `parallelism` ranges are buffered in memory at any time.
+    ///
+    /// Registries without `Accept-Ranges: bytes` are read with a single request.
+    pub async fn get_blob_stream_ranged(
+        &self,
+        name: &str,
+        digest: &str,
+        chunk_size: u64,
+        parallelism: usize,
+    ) -> Result<impl Stream<Item = Result<Bytes>>> {
+        let digest = ContentDigest::try_new(digest.to_string())?;
+        let chunk_size = chunk_size.max(1);
+        let parallelism = parallelism.max(1);
+
+        let size = match self.blob_range_support(name, &digest).await? {
+            Some(size) => size,
+            None => {
+                debug!("Registry does not support range requests for {}, using a single request", digest);
+                let res = self.get_blob_response(name, &digest, 0).await?;
+                if !res.status().is_success() {
+                    return Err(registry_error(res).await);
+                }
+                return Ok(future::Either::Left(verified_stream(res, digest.hasher())));
             }
+        };
 
-            let status = res.status();
+        let ranges = std::iter::successors(Some(0), move |start: &u64| start.checked_add(chunk_size))
+            .take_while(move |start| *start < size)
+            .map(move |start| (start, start.saturating_add(chunk_size).min(size) - 1));
+        trace!("Fetching {} bytes of {} in ranges of {} bytes", size, digest, chunk_size);
+
+        let client = self.clone();
+        let name = name.to_string();
+        let range_digest = digest.clone();
+        let chunks = stream::iter(ranges)
+            .map(move |(start, end)| {
+                let client = client.clone();
+                let name = name.clone();
+                let digest = range_digest.clone();
+                async move { client.get_blob_range(&name, &digest, start, end).await }
+            })
+            .buffered(parallelism);
+
+        let mut hasher = digest.hasher();
+        Ok(future::Either::Right(try_stream! {
+            futures::pin_mut!(chunks);
+            while let Some(chunk) = chunks.next().await {
+                let chunk = chunk?;
+                hasher.update(&chunk);
+                yield chunk;
+            }
+
+            trace!("Successfully received blob with {} bytes ", size);
+            hasher.verify()?;
+        }))
+    }
+
+    /// Retrieve blob with progress
+    pub async fn get_blob_with_progress(&self, name: &str, digest: &str, sender: Option<Sender<u64>>) -> Result<Vec<u8>> {
+        let mut stream = Box::pin(self.get_blob_stream(name, digest).await?);
+
+        let mut body_vec: Vec<u8> = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            if let Some(send) = &sender {
+                // Progress is best effort, the receiver may be gone already.
+                let _ = send.send(chunk.len() as u64);
+            };
+            body_vec.extend_from_slice(&chunk);
+        }
+
+        Ok(body_vec)
+    }
 
-            let mut stream = res.bytes_stream();
-
-            let mut body_vec: Vec<u8> = Vec::new();
-            while let Some(item) = stream.next().await {
-                let chunk = match item {
-                    Ok(b) => { b }
-                    Err(e) => {
-                        error!("Unable to download blob: {}", e);
-                        if let Some(send) = sender {
-                            drop(send);
-                        };
-                        return Err(Error::DownloadFailed);
+    /// Retrieve blob with progress into a file named after its digest in `target_dir`.
+    ///
+    /// The blob is written to a `.partial` file next to the target first,
+    /// which is only renamed into place once the digest has been verified.
+    /// If a download was interrupted, the next call resumes it from the
+    /// bytes already present in the `.partial` file, given the registry
+    /// supports range requests. Bytes already on disk are reported once
+    /// through `sender` when resuming.
+    pub async fn get_blob_with_progress_file(&self, name: &str, hash: &str, sender: Option<Sender<u64>>, target_dir: &Path) -> Result<PathBuf> {
+        let digest = ContentDigest::try_new(hash.to_string())?;
+        std::fs::create_dir_all(target_dir)?;
+        let target = target_dir.join(hash);
+        let partial = target_dir.join(format!("{}.partial", hash));
+
+        match self.download_blob_to(name, &digest, sender, &partial).await {
+            Ok(()) => {
+                std::fs::rename(&partial, &target)?;
+                Ok(target)
+            }
+            Err(e) => {
+                // Keep partial content around for resuming, unless it is corrupt.
+                if let Error::ContentDigestParse(ContentDigestError::Verify { .. }) = e {
+                    if let Err(rm) = std::fs::remove_file(&partial) {
+                        debug!("Unable to remove {:?}: {}", partial, rm);
                     }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Download a blob into `path`, resuming from its current content.
+    ///
+    /// File IO, including hashing the content already on disk, runs on the
+    /// blocking thread pool of the tokio runtime.
+    async fn download_blob_to(&self, name: &str, digest: &ContentDigest, sender: Option<Sender<u64>>, path: &Path) -> Result<()> {
+        let (partial, mut hasher, offset) = {
+            let path = path.to_path_buf();
+            let mut hasher = digest.hasher();
+            blocking(move || {
+                let mut file = match OpenOptions::new().read(true).write(true).open(&path) {
+                    Ok(file) => file,
+                    Err(e) if e.kind() == ErrorKind::NotFound => return Ok((None, hasher, 0)),
+                    Err(e) => return Err(e.into()),
                 };
+                let offset = hash_file(&mut file, &mut hasher)?;
+                Ok((Some(file), hasher, offset))
+            })
+            .await?
+        };
 
+        let mut res = self.get_blob_response(name, digest, offset).await?;
+        let mut restart = false;
+        match res.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                let start = content_range_start(&res)?;
+                if start != offset {
+                    return Err(Error::InvalidHeader(
+                        header::CONTENT_RANGE.to_string(),
+                        format!("expected range starting at {}, got {}", offset, start),
+                    ));
+                }
+                trace!("Resuming download of {} at offset {}", digest, offset);
                 if let Some(send) = &sender {
-                    send.send(chunk.len() as u64).unwrap();
+                    let _ = send.send(offset);
                 };
-                body_vec.append(&mut chunk.to_vec());
             }
-            let len = body_vec.len();
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                // The partial file may already hold the whole blob.
+                if hasher.clone().verify().is_ok() {
+                    trace!("Partial download of {} is already complete", digest);
+                    if let Some(send) = &sender {
+                        let _ = send.send(offset);
+                    };
+                    return Ok(());
+                }
+                debug!("Cannot resume download of {} at offset {}, restarting", digest, offset);
+                restart = true;
+                res = self.get_blob_response(name, digest, 0).await?;
+            }
+            StatusCode::OK if offset > 0 => {
+                debug!("Registry ignored range request for {}, restarting", digest);
+                restart = true;
+            }
+            _ => {}
+        }
+
+        if !res.status().is_success() {
+            return Err(registry_error(res).await);
+        }
+
+        if restart {
+            hasher = digest.hasher();
+        }
+        // The partial file is only created once the registry serves the blob.
+        let mut file = {
+            let path = path.to_path_buf();
+            blocking(move || match partial {
+                Some(mut file) if restart => {
+                    truncate(&mut file)?;
+                    Ok(file)
+                }
+                Some(file) => Ok(file),
+                None => Ok(File::create(&path)?),
+            })
+            .await?
+        };
 
-            if let Some(send) = sender {
-                drop(send);
+        // Chunks are buffered, so that writes are handed to the blocking pool in batches.
+        let mut buf = Vec::with_capacity(WRITE_BUFFER_SIZE);
+        let mut stream = Box::pin(verified_stream(res, hasher));
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            if let Some(send) = &sender {
+                // Progress is best effort, the receiver may be gone already.
+                let _ = send.send(chunk.len() as u64);
             };
-            if status.is_success() {
-                trace!("Successfully received blob with {} bytes ", len);
-                Ok(body_vec)
-            } else if status.is_client_error() {
-                Err(Error::Client {
-                    status,
-                    len,
-                    body: body_vec,
+            buf.extend_from_slice(&chunk);
+            if buf.len() >= WRITE_BUFFER_SIZE {
+                let data = std::mem::replace(&mut buf, Vec::with_capacity(WRITE_BUFFER_SIZE));
+                file = blocking(move || {
+                    file.write_all(&data)?;
+                    Ok(file)
                 })
-            } else {
-                // We only want to handle success and client errors here
-                error!(
-                    "Received unexpected HTTP status '{}' after fetching the body. Please submit a bug report.",
-                    status
-                );
-                Err(Error::UnexpectedHttpStatus(status))
+                .await?;
             }
-        }?;
+        }
 
-        digest.try_verify(&blob)?;
-        Ok(blob.to_vec())
+        blocking(move || {
+            file.write_all(&buf)?;
+            file.sync_all()?;
+            Ok(())
+        })
+        .await
     }
 
-    /// Retrieve blob with progress
-    pub async fn get_blob_with_progress_file(&self, name: &str, hash: &str, sender: Option<Sender<u64>>, target_dir: &Path) -> Result<PathBuf> {
-        let digest = ContentDigest::try_new(hash.to_string())?;
-        let mut target = target_dir.to_path_buf();
-        std::fs::create_dir_all(&target);
-        target.push(hash);
+    /// Discover the blob size, if the registry supports range requests for it.
+    async fn blob_range_support(&self, name: &str, digest: &ContentDigest) -> Result<Option<u64>> {
+        let ep = format!("{}/v2/{}/blobs/{}", self.base_url, name, digest);
+        let url = reqwest::Url::parse(&ep)?;
+
+        let res = self.send_blob_request(Method::HEAD, url, HeaderMap::new()).await?;
 
+        if res.status() != StatusCode::OK {
+            return Err(registry_error(res).await);
+        }
+
+        let headers = res.headers();
+        let accepts_ranges = headers
+            .get(header::ACCEPT_RANGES)
+            .and_then(|v| v.to_str().ok())
+            .map_or(false, |v| v.split(',').any(|unit| unit.trim() == "bytes"));
+        let size = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+
+        match (accepts_ranges, size) {
+            (true, Some(size)) if size > 0 => Ok(Some(size)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Fetch the inclusive byte range `start..=end` of a blob.
+    async fn get_blob_range(&self, name: &str, digest: &ContentDigest, start: u64, end: u64) -> Result<Bytes> {
         let ep = format!("{}/v2/{}/blobs/{}", self.base_url, name, digest);
         let url = reqwest::Url::parse(&ep)?;
 
-        let res = self.build_reqwest(Method::GET, url.clone()).send().await?;
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, range_header(&format!("bytes={}-{}", start, end)));
+        let res = self.send_blob_request(Method::GET, url, headers).await?;
 
-        trace!("GET {} status: {}", res.url(), res.status());
-        let status = res.status();
-        // Let client errors through to populate them with the body
-        if !(status.is_success() || status.is_client_error()) {
-            if let Some(send) = sender {
-                drop(send);
-            };
-            return Err(Error::UnexpectedHttpStatus(status));
+        if res.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(registry_error(res).await);
+        }
+        let got = content_range_start(&res)?;
+        if got != start {
+            return Err(Error::InvalidHeader(
+                header::CONTENT_RANGE.to_string(),
+                format!("expected range starting at {}, got {}", start, got),
+            ));
+        }
+
+        let chunk = res.bytes().await?;
+        if chunk.len() as u64 != end - start + 1 {
+            error!("Expected {} bytes for range {}-{} of {}, got {}", end - start + 1, start, end, digest, chunk.len());
+            return Err(Error::DownloadFailed);
+        }
+        Ok(chunk)
+    }
+
+    /// Request a blob, starting at `offset` if it is not zero.
+    async fn get_blob_response(&self, name: &str, digest: &ContentDigest, offset: u64) -> Result<reqwest::Response> {
+        let ep = format!("{}/v2/{}/blobs/{}", self.base_url, name, digest);
+        let url = reqwest::Url::parse(&ep)?;
+
+        let mut headers = HeaderMap::new();
+        if offset > 0 {
+            headers.insert(header::RANGE, range_header(&format!("bytes={}-", offset)));
         }
+        self.send_blob_request(Method::GET, url, headers).await
+    }
 
-        let status = res.status();
+    /// Resolve the location a blob can be downloaded from.
+    ///
+    /// Registries often redirect blob downloads to a storage backend, e.g.
+    /// a signed S3 or GCS URL. The first redirect target is returned
+    /// without following it, so that callers can fetch or cache it
+    /// directly; such URLs must be fetched without registry credentials.
+    /// If the registry, or one of its mirrors, serves the blob itself, its URL is returned.
+    /// The location is resolved with a `GET` request, as signed URLs are
+    /// usually only valid for the method they were issued for; the response
+    /// is dropped without reading its body.
+    pub async fn get_blob_location(&self, name: &str, digest: &str) -> Result<Url> {
+        let digest = ContentDigest::try_new(digest.to_string())?;
 
-        let mut stream = res.bytes_stream();
+        let ep = format!("{}/v2/{}/blobs/{}", self.base_url, name, digest);
+        let url = reqwest::Url::parse(&ep)?;
 
+        let res = self
+            .send_blob_reqwest(self.build_blob_reqwest(Method::GET, url.clone(), true))
+            .await?;
 
-        let mut file = OpenOptions::new().write(true).truncate(true).create(true).open(&target).unwrap();
-        let mut len: usize = 0;
-        while let Some(item) = stream.next().await {
-            let chunk = match item {
-                Ok(b) => { b }
-                Err(e) => {
-                    error!("Unable to download blob: {}", e);
-                    if let Some(send) = sender {
-                        drop(send);
-                    };
-                    return Err(Error::DownloadFailed);
+        trace!("GET {} status: {}", res.url(), res.status());
+
+        match redirect_target(&res)? {
+            Some(target) => {
+                trace!("Blob {} is located at {}", digest, target);
+                Ok(target)
+            }
+            None if res.status().is_success() => Ok(res.url().clone()),
+            None => Err(registry_error(res).await),
+        }
+    }
+
+    /// Send a blob request, following redirects.
+    ///
+    /// Credentials are only sent to the registry itself. Once a redirect
+    /// crosses to another host, the `Authorization` header is dropped for
+    /// the rest of the chain, as the target is usually a storage backend
+    /// authorizing the request through a signed URL.
+    async fn send_blob_request(&self, method: Method, url: Url, headers: HeaderMap) -> Result<reqwest::Response> {
+        let mut method = method;
+        let mut with_auth = url.origin() == Url::parse(&self.base_url)?.origin();
+        let mut url = url;
+
+        for _ in 0..=MAX_BLOB_REDIRECTS {
+            let req = self
+                .build_blob_reqwest(method.clone(), url.clone(), with_auth)
+                .headers(headers.clone());
+            let res = self.send_blob_reqwest(req).await?;
+
+            let target = match redirect_target(&res)? {
+                Some(target) => target,
+                None => {
+                    trace!("{} {} status: {}", method, res.url(), res.status());
+                    return Ok(res);
                 }
             };
 
-            if let Some(send) = &sender {
-                send.send(chunk.len() as u64).unwrap();
-            };
+            trace!("{} {} redirected with {} to {}", method, url, res.status(), target);
+            if with_auth && target.origin() != url.origin() {
+                trace!("Dropping credentials for redirect to {}", target.origin().ascii_serialization());
+                with_auth = false;
+            }
+            if res.status() == StatusCode::SEE_OTHER && method != Method::HEAD {
+                method = Method::GET;
+            }
+            url = target;
+        }
+
+        Err(Error::TooManyRedirects(url.to_string()))
+    }
+}
+
+/// Maximum number of redirects followed for a single blob request.
+const MAX_BLOB_REDIRECTS: usize = 10;
+
+/// Amount of downloaded content buffered before writing it to disk.
+const WRITE_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Run blocking file IO on the blocking thread pool of the tokio runtime.
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.map_err(std::io::Error::from)?
+}
+
+fn range_header(range: &str) -> HeaderValue {
+    HeaderValue::from_str(range).expect("byte range is always valid header value")
+}
+
+/// Target of a redirect response, resolved against the request URL.
+fn redirect_target(res: &reqwest::Response) -> Result<Option<Url>> {
+    match res.status() {
+        StatusCode::MOVED_PERMANENTLY
+        | StatusCode::FOUND
+        | StatusCode::SEE_OTHER
+        | StatusCode::TEMPORARY_REDIRECT
+        | StatusCode::PERMANENT_REDIRECT => {}
+        _ => return Ok(None),
+    }
+
+    let location = res
+        .headers()
+        .get(header::LOCATION)
+        .ok_or_else(|| Error::MissingHeader(header::LOCATION.to_string()))?
+        .to_str()?;
+    Ok(Some(res.url().join(location)?))
+}
+
+/// Stream the response body, verifying it with `hasher` at the end.
+fn verified_stream(res: reqwest::Response, mut hasher: ContentHasher) -> impl Stream<Item = Result<Bytes>> {
+    let mut chunks = res.bytes_stream();
+
+    try_stream! {
+        let mut len: usize = 0;
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            hasher.update(&chunk);
             len += chunk.len();
-            file.write_all(&chunk).unwrap();
+            yield chunk;
         }
 
-        if let Some(send) = sender {
-            drop(send);
-        };
-        if status.is_success() {
-            trace!("Successfully received blob with {} bytes ", len);
-            // digest.try_verify(&blob)?;
-            Ok(target.clone())
-        } else if status.is_client_error() {
-            Err(Error::Client {
-                status,
-                len,
-                body: vec![],
-            })
-        } else {
-            // We only want to handle success and client errors here
-            error!(
-                    "Received unexpected HTTP status '{}' after fetching the body. Please submit a bug report.",
-                    status
-                );
-            Err(Error::UnexpectedHttpStatus(status))
+        trace!("Successfully received blob with {} bytes ", len);
+        hasher.verify()?;
+    }
+}
+
+/// Feed the content of `file` into `hasher`, returning its length.
+fn hash_file(file: &mut File, hasher: &mut ContentHasher) -> Result<u64> {
+    let mut buf = vec![0; 64 * 1024];
+    let mut len: u64 = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(len);
         }
+        hasher.update(&buf[..n]);
+        len += n as u64;
     }
 }
+
+fn truncate(file: &mut File) -> Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(())
+}
+
+/// Parse the first byte position from a `Content-Range: bytes <start>-<end>/<size>` header.
+fn content_range_start(res: &reqwest::Response) -> Result<u64> {
+    let value = res
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .ok_or_else(|| Error::MissingHeader(header::CONTENT_RANGE.to_string()))?
+        .to_str()?;
+    value
+        .trim_start_matches("bytes ")
+        .split('-')
+        .next()
+        .and_then(|start| start.parse().ok())
+        .ok_or_else(|| Error::InvalidHeader(header::CONTENT_RANGE.to_string(), value.to_string()))
+}
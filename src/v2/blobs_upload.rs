@@ -0,0 +1,270 @@
+use crate::errors::{Error, Result};
+use crate::v2::*;
+use reqwest::{self, header, Method, StatusCode, Url};
+
+/// An in-progress blob upload session.
+///
+/// A session is started with `Client::start_blob_upload` and keeps track of
+/// the location to use for the next request and of the number of bytes the
+/// registry acknowledged so far.
+#[derive(Clone, Debug)]
+pub struct BlobUpload {
+    name: String,
+    location: Url,
+    uuid: Option<String>,
+    offset: u64,
+}
+
+impl BlobUpload {
+    /// Repository this session uploads to.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// URL for the next request in this session.
+    pub fn location(&self) -> &Url {
+        &self.location
+    }
+
+    /// Session UUID, if the registry advertised one.
+    pub fn uuid(&self) -> Option<&str> {
+        self.uuid.as_deref()
+    }
+
+    /// Number of bytes the registry has acknowledged so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Build a session from the `Location` and `Docker-Upload-UUID` headers of a response.
+    fn try_from_response(name: &str, offset: u64, res: &reqwest::Response) -> Result<Self> {
+        let headers = res.headers();
+        let location = headers
+            .get(header::LOCATION)
+            .ok_or_else(|| Error::MissingHeader("Location".to_string()))?
+            .to_str()?;
+        // The location may be relative to the registry endpoint.
+        let location = res.url().join(location)?;
+        let uuid = match headers.get("docker-upload-uuid") {
+            Some(uuid) => Some(uuid.to_str()?.to_string()),
+            None => None,
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            location,
+            uuid,
+            offset,
+        })
+    }
+}
+
+/// Outcome of a cross-repository blob mount.
+#[derive(Debug)]
+pub enum BlobMount {
+    /// The blob has been mounted into the target repository, with the given digest.
+    Mounted(String),
+    /// The registry did not mount the blob and started an upload session instead.
+    Upload(BlobUpload),
+}
+
+impl Client {
+    /// Start a new blob upload session.
+    pub async fn start_blob_upload(&self, name: &str) -> Result<BlobUpload> {
+        let url = {
+            let ep = format!("{}/v2/{}/blobs/uploads/", self.base_url, name);
+            reqwest::Url::parse(&ep)?
+        };
+
+        let res = self.send_request(self.build_reqwest(Method::POST, url)).await?;
+
+        let status = res.status();
+        trace!("POST {} status: {}", res.url(), status);
+
+        match status {
+            StatusCode::ACCEPTED => BlobUpload::try_from_response(name, 0, &res),
+            _ => Err(registry_error(res).await),
+        }
+    }
+
+    /// Mount a blob from repository `from` into repository `name`.
+    ///
+    /// This requires pull access on `from` and push access on `name`,
+    /// see `mount_scopes` for the matching token scopes.
+    /// Registries which cannot mount the blob fall back to a regular
+    /// upload session, which is returned as `BlobMount::Upload`.
+    pub async fn mount_blob(&self, name: &str, digest: &str, from: &str) -> Result<BlobMount> {
+        let digest = ContentDigest::try_new(digest.to_string())?;
+
+        let url = {
+            let ep = format!("{}/v2/{}/blobs/uploads/", self.base_url, name);
+            let mut url = reqwest::Url::parse(&ep)?;
+            url.query_pairs_mut()
+                .append_pair("mount", &digest.to_string())
+                .append_pair("from", from);
+            url
+        };
+
+        let res = self.send_request(self.build_reqwest(Method::POST, url)).await?;
+
+        let status = res.status();
+        trace!("POST {} status: {}", res.url(), status);
+
+        match status {
+            StatusCode::CREATED => {
+                trace!("Mounted blob {} from {}", digest, from);
+                Ok(BlobMount::Mounted(digest.to_string()))
+            }
+            StatusCode::ACCEPTED => {
+                trace!("Blob {} not mounted, falling back to upload", digest);
+                BlobUpload::try_from_response(name, 0, &res).map(BlobMount::Upload)
+            }
+            _ => Err(registry_error(res).await),
+        }
+    }
+
+    /// Upload a chunk of data to an upload session.
+    ///
+    /// The chunk is appended at the current offset of the session.
+    /// The returned session must be used for further requests.
+    pub async fn upload_blob_chunk(
+        &self,
+        upload: BlobUpload,
+        chunk: Vec<u8>,
+    ) -> Result<BlobUpload> {
+        if chunk.is_empty() {
+            return Ok(upload);
+        }
+
+        let start = upload.offset;
+        let end = start + chunk.len() as u64 - 1;
+
+        let req = self
+            .build_reqwest(Method::PATCH, upload.location.clone())
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(header::CONTENT_RANGE, format!("{}-{}", start, end))
+            .body(chunk);
+        let res = self.send_request(req).await?;
+
+        let status = res.status();
+        trace!("PATCH {} status: {}", res.url(), status);
+
+        if status != StatusCode::ACCEPTED {
+            return Err(registry_error(res).await);
+        }
+
+        // `Range` is inclusive and always starts at 0, e.g. `0-1023` after 1024 bytes.
+        let offset = match res.headers().get(header::RANGE) {
+            Some(range) => {
+                let range = range.to_str()?;
+                range
+                    .trim_start_matches("bytes=")
+                    .splitn(2, '-')
+                    .nth(1)
+                    .and_then(|end| end.parse::<u64>().ok())
+                    .map(|end| end + 1)
+                    .ok_or_else(|| Error::InvalidHeader("Range".to_string(), range.to_string()))?
+            }
+            None => end + 1,
+        };
+        trace!("Blob upload offset: {}", offset);
+
+        BlobUpload::try_from_response(&upload.name, offset, &res)
+    }
+
+    /// Complete an upload session, optionally sending a final chunk of data.
+    ///
+    /// On success the digest computed by the registry is returned, after
+    /// checking that it matches the expected `digest`.
+    pub async fn finish_blob_upload(
+        &self,
+        upload: BlobUpload,
+        digest: &str,
+        chunk: Vec<u8>,
+    ) -> Result<String> {
+        let digest = ContentDigest::try_new(digest.to_string())?;
+
+        let mut url = upload.location.clone();
+        url.query_pairs_mut()
+            .append_pair("digest", &digest.to_string());
+
+        let mut req = self
+            .build_reqwest(Method::PUT, url)
+            .header(header::CONTENT_TYPE, "application/octet-stream");
+        // A monolithic upload sends the whole blob here, without any range.
+        if !chunk.is_empty() && upload.offset > 0 {
+            let end = upload.offset + chunk.len() as u64 - 1;
+            req = req.header(header::CONTENT_RANGE, format!("{}-{}", upload.offset, end));
+        }
+
+        let res = self.send_request(req.body(chunk)).await?;
+
+        let status = res.status();
+        trace!("PUT {} status: {}", res.url(), status);
+
+        if status != StatusCode::CREATED {
+            return Err(registry_error(res).await);
+        }
+
+        match res.headers().get("docker-content-digest") {
+            Some(content_digest_value) => {
+                let uploaded = ContentDigest::try_new(content_digest_value.to_str()?.to_string())?;
+                if uploaded != digest {
+                    return Err(ContentDigestError::Verify {
+                        expected: digest,
+                        got: uploaded,
+                    }
+                    .into());
+                }
+            }
+            None => debug!("cannot find uploaded blob digest in headers"),
+        };
+
+        trace!("Successfully uploaded blob {}", digest);
+        Ok(digest.to_string())
+    }
+
+    /// Cancel an upload session, discarding the data uploaded so far.
+    pub async fn cancel_blob_upload(&self, upload: BlobUpload) -> Result<()> {
+        let res = self
+            .send_request(self.build_reqwest(Method::DELETE, upload.location.clone()))
+            .await?;
+
+        let status = res.status();
+        trace!("DELETE {} status: {}", res.url(), status);
+
+        match status {
+            StatusCode::NO_CONTENT | StatusCode::OK => Ok(()),
+            _ => Err(registry_error(res).await),
+        }
+    }
+
+    /// Upload a blob in a single request.
+    ///
+    /// The blob is checked against `digest` before anything is sent.
+    pub async fn upload_blob(&self, name: &str, digest: &str, blob: &[u8]) -> Result<String> {
+        ContentDigest::try_new(digest.to_string())?.try_verify(blob)?;
+
+        let upload = self.start_blob_upload(name).await?;
+        self.finish_blob_upload(upload, digest, blob.to_vec()).await
+    }
+
+    /// Upload a blob in chunks of at most `chunk_size` bytes.
+    ///
+    /// The blob is checked against `digest` before anything is sent.
+    pub async fn upload_blob_chunked(
+        &self,
+        name: &str,
+        digest: &str,
+        blob: &[u8],
+        chunk_size: usize,
+    ) -> Result<String> {
+        ContentDigest::try_new(digest.to_string())?.try_verify(blob)?;
+
+        let mut upload = self.start_blob_upload(name).await?;
+        for chunk in blob.chunks(chunk_size.max(1)) {
+            upload = self.upload_blob_chunk(upload, chunk.to_vec()).await?;
+        }
+        self.finish_blob_upload(upload, digest, vec![]).await
+    }
+}
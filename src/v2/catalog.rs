@@ -23,13 +23,13 @@ impl v2::Client {
             };
             let ep = format!("{}/v2/_catalog{}", self.base_url.clone(), suffix);
 
-            reqwest::Url::parse(&ep).map_err(|err| crate::Error::from(err))
+            reqwest::Url::parse(&ep).map_err(crate::Error::from)
         };
 
         try_stream! {
             let req = self.build_reqwest(Method::GET, url?);
 
-            let catalog = fetch_catalog(req).await?;
+            let catalog = fetch_catalog(self, req).await?;
 
             for repo in catalog.repositories {
                 yield repo;
@@ -38,14 +38,14 @@ impl v2::Client {
     }
 }
 
-async fn fetch_catalog(req: RequestBuilder) -> Result<Catalog> {
-    let r = req.send().await?;
+async fn fetch_catalog(client: &v2::Client, req: RequestBuilder) -> Result<Catalog> {
+    let r = client.send_request(req).await?;
     let status = r.status();
     trace!("Got status: {:?}", status);
     match status {
         StatusCode::OK => r
             .json::<Catalog>()
             .await.map_err(Into::into),
-        _ => Err(crate::Error::UnexpectedHttpStatus(status)),
+        _ => Err(v2::registry_error(r).await),
     }
 }
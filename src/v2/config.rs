@@ -1,19 +1,44 @@
+use crate::credentials::{CredentialProvider, Credentials};
 use crate::v2::*;
 
 /// Configuration for a `Client`.
-#[derive(Debug)]
 pub struct Config {
     index: String,
     insecure_registry: bool,
     user_agent: Option<String>,
     username: Option<String>,
     password: Option<String>,
+    identity_token: Option<String>,
+    force_oauth: bool,
+    anonymous_first: bool,
+    credential_provider: Option<Arc<dyn CredentialProvider>>,
+    auth: Option<auth::Auth>,
+    mirrors: Vec<Mirror>,
     accept_invalid_certs: bool,
 }
 
-impl Config {
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Config")
+            .field("index", &self.index)
+            .field("insecure_registry", &self.insecure_registry)
+            .field("user_agent", &self.user_agent)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("identity_token", &self.identity_token.as_deref().map(auth::mask))
+            .field("force_oauth", &self.force_oauth)
+            .field("anonymous_first", &self.anonymous_first)
+            .field("credential_provider", &self.credential_provider)
+            .field("auth", &self.auth)
+            .field("mirrors", &self.mirrors)
+            .field("accept_invalid_certs", &self.accept_invalid_certs)
+            .finish()
+    }
+}
+
+impl Default for Config {
     /// Initialize `Config` with default values.
-    pub fn default() -> Self {
+    fn default() -> Self {
         Self {
             index: "registry-1.docker.io".into(),
             insecure_registry: false,
@@ -21,9 +46,17 @@ impl Config {
             user_agent: Some(crate::USER_AGENT.to_owned()),
             username: None,
             password: None,
+            identity_token: None,
+            force_oauth: false,
+            anonymous_first: false,
+            credential_provider: None,
+            auth: None,
+            mirrors: vec![],
         }
     }
+}
 
+impl Config {
     /// Set registry service to use (vhost or IP).
     pub fn registry(mut self, reg: &str) -> Self {
         self.index = reg.to_owned();
@@ -42,6 +75,22 @@ impl Config {
         self
     }
 
+    /// Add a mirror of the registry.
+    ///
+    /// Manifests and blobs are pulled from the mirrors first, in the order
+    /// they were added. The next mirror, and finally the registry itself,
+    /// is tried when a mirror does not have the content or cannot be reached.
+    pub fn mirror(mut self, mirror: Mirror) -> Self {
+        self.mirrors.push(mirror);
+        self
+    }
+
+    /// Set the mirrors of the registry, replacing those added before.
+    pub fn mirrors(mut self, mirrors: Vec<Mirror>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
     /// Set the user-agent to be used for registry authentication.
     pub fn user_agent(mut self, user_agent: Option<String>) -> Self {
         self.user_agent = user_agent;
@@ -60,15 +109,107 @@ impl Config {
         self
     }
 
+    /// Set the identity token to be used for registry authentication.
+    ///
+    /// An identity token is an OAuth2 refresh token, as stored by `docker login`
+    /// in the `identitytoken` field of its configuration.
+    pub fn identity_token(mut self, identity_token: Option<String>) -> Self {
+        self.identity_token = identity_token;
+        self
+    }
+
+    /// Set whether to request tokens with the OAuth2 password grant.
+    ///
+    /// Otherwise credentials are sent with Basic authentication to the
+    /// token endpoint. Endpoints which do not support OAuth2 fall back to it.
+    pub fn force_oauth(mut self, force_oauth: bool) -> Self {
+        self.force_oauth = force_oauth;
+        self
+    }
+
     /// Read credentials from a JSON config file
+    ///
+    /// Credential helpers configured in the file are run to get them.
     pub fn read_credentials<T: ::std::io::Read>(mut self, reader: T) -> Self {
-        if let Ok(creds) = crate::get_credentials(reader, &self.index) {
-            self.username = creds.0;
-            self.password = creds.1;
+        let creds = crate::credentials::DockerConfig::from_reader(reader)
+            .and_then(|config| config.credentials(&self.index));
+        match creds {
+            Ok(Some(creds)) => self = self.credentials(creds),
+            Ok(None) => trace!("No credentials found for {}", self.index),
+            Err(e) => warn!("Failed to read credentials for {}: {}", self.index, e),
         };
         self
     }
 
+    /// Discover credentials in the usual configuration files.
+    ///
+    /// See `credentials::auth_file_paths` for the files searched. Entries
+    /// scoped to `repository` or its namespaces are preferred.
+    pub fn discover_credentials(mut self, repository: Option<&str>) -> Self {
+        match crate::credentials::discover_credentials(&self.index, repository) {
+            Ok(Some(creds)) => self = self.credentials(creds),
+            Ok(None) => trace!("No credentials found for {}", self.index),
+            Err(e) => warn!("Failed to discover credentials for {}: {}", self.index, e),
+        };
+        self
+    }
+
+    /// Set the credentials to be used for registry authentication.
+    ///
+    /// A bearer token is set as the authentication to start with, as with
+    /// `bearer_token`; a credential provider is kept and asked once the
+    /// token is rejected. Other credentials are only used when no credential
+    /// provider is set.
+    pub fn credentials(mut self, creds: Credentials) -> Self {
+        match creds {
+            Credentials::Basic { username, password } => {
+                self.username = Some(username);
+                self.password = Some(password);
+            }
+            Credentials::IdentityToken(token) => {
+                self.identity_token = Some(token);
+            }
+            Credentials::Bearer(token) => {
+                return self.bearer_token(token);
+            }
+        };
+        self
+    }
+
+    /// Set whether to try an anonymous token before sending credentials.
+    ///
+    /// Credentials are only sent if the `access` claims of the anonymous
+    /// token do not grant the requested scopes, so that pulls of public
+    /// images are not accounted against the user. Registries asking for
+    /// Basic authentication are accessed anonymously without credentials.
+    pub fn anonymous_first(mut self, anonymous_first: bool) -> Self {
+        self.anonymous_first = anonymous_first;
+        self
+    }
+
+    /// Set the authentication to start with, e.g. a bearer token obtained elsewhere.
+    ///
+    /// Once it is rejected by the registry, the client authenticates again
+    /// with its credentials, if any.
+    pub fn auth(mut self, auth: Option<auth::Auth>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Set a bearer token obtained elsewhere, e.g. from a CI broker.
+    pub fn bearer_token(self, token: String) -> Self {
+        self.auth(Some(auth::Auth::Bearer(auth::BearerAuth::new(token))))
+    }
+
+    /// Set the provider asked for credentials whenever the client authenticates.
+    ///
+    /// It takes precedence over the username, password and identity token,
+    /// but not over the authentication to start with, see `auth`.
+    pub fn credential_provider(mut self, provider: Arc<dyn CredentialProvider>) -> Self {
+        self.credential_provider = Some(provider);
+        self
+    }
+
     /// Return a `Client` to interact with a v2 registry.
     pub fn build(self) -> Result<Client> {
         let base = if self.insecure_registry {
@@ -92,14 +233,29 @@ impl Config {
         let client = reqwest::ClientBuilder::new()
             .danger_accept_invalid_certs(self.accept_invalid_certs)
             .build()?;
+        // Blob requests follow redirects manually, see `Client::send_blob_request`.
+        let blob_client = reqwest::ClientBuilder::new()
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
 
         let c = Client {
             base_url: base,
             credentials: creds,
             index: self.index,
             user_agent: self.user_agent,
-            auth: None,
-            client: client,
+            auth: self.auth,
+            client,
+            blob_client,
+            force_oauth: self.force_oauth,
+            anonymous_first: self.anonymous_first,
+            credential_provider: self.credential_provider,
+            mirrors: self
+                .mirrors
+                .into_iter()
+                .map(Mirror::build)
+                .collect::<Result<_>>()?,
+            tokens: Arc::new(Mutex::new(auth::TokenCache::new(self.identity_token))),
         };
         Ok(c)
     }
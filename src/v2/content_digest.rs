@@ -48,6 +48,24 @@ impl ContentDigest {
         })
     }
 
+    /// from_content hashes the input slice and creates a ContentDigest instance for it
+    pub fn from_content(input: &[u8]) -> Self {
+        let algorithm = DigestAlgorithm::Sha256;
+        let hash = algorithm.hash(input);
+        Self::try_new(hash).expect("hash output always carries the algorithm prefix")
+    }
+
+    /// hasher creates a ContentHasher to verify content against this digest chunk by chunk
+    pub fn hasher(&self) -> ContentHasher {
+        let hasher = match self.algorithm {
+            DigestAlgorithm::Sha256 => sha2::Sha256::new(),
+        };
+        ContentHasher {
+            expected: self.clone(),
+            hasher,
+        }
+    }
+
     /// try_verify hashes the input slice and compares it with the digest stored in this instance
     ///
     /// Success depends on the result of the comparison
@@ -67,6 +85,36 @@ impl ContentDigest {
     }
 }
 
+/// ContentHasher incrementally hashes content which is expected to match a ContentDigest
+#[derive(Clone, Debug)]
+pub struct ContentHasher {
+    expected: ContentDigest,
+    hasher: sha2::Sha256,
+}
+
+impl ContentHasher {
+    /// update feeds the next chunk of content into the hasher
+    pub fn update(&mut self, input: &[u8]) {
+        self.hasher.update(input);
+    }
+
+    /// verify finalizes the hash and compares it with the expected digest
+    pub fn verify(self) -> std::result::Result<(), ContentDigestError> {
+        let hash = format!("{}:{:x}", self.expected.algorithm, self.hasher.finalize());
+        let layer_digest = ContentDigest::try_new(hash)?;
+
+        if self.expected != layer_digest {
+            return Err(ContentDigestError::Verify {
+                expected: self.expected,
+                got: layer_digest,
+            });
+        }
+
+        trace!("content verification succeeded for '{}'", &layer_digest);
+        Ok(())
+    }
+}
+
 impl std::fmt::Display for ContentDigest {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}:{}", self.algorithm, self.digest)
@@ -91,11 +139,8 @@ mod tests {
 
     #[test]
     fn try_new_succeeds_with_correct_digest() -> Fallible<()> {
-        for correct_digest in
-            &["sha256:0000000000000000000000000000000000000000000000000000000000000000"]
-        {
-            ContentDigest::try_new(correct_digest.to_string())?;
-        }
+        let correct_digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+        ContentDigest::try_new(correct_digest.to_string())?;
 
         Ok(())
     }
@@ -119,21 +164,47 @@ mod tests {
     #[test]
     fn try_verify_succeeds_with_same_content() -> Fallible<()> {
         let blob: &[u8] = b"somecontent";
-        let digest = DigestAlgorithm::Sha256.hash(&blob);
+        let digest = DigestAlgorithm::Sha256.hash(blob);
 
         ContentDigest::try_new(digest)?
-            .try_verify(&blob)
+            .try_verify(blob)
             .map_err(Into::into)
     }
 
+    #[test]
+    fn from_content_matches_hash() -> Fallible<()> {
+        let blob: &[u8] = b"somecontent";
+        let digest = DigestAlgorithm::Sha256.hash(blob);
+
+        assert_eq!(ContentDigest::try_new(digest)?, ContentDigest::from_content(blob));
+        Ok(())
+    }
+
+    #[test]
+    fn hasher_verifies_chunked_content() -> Fallible<()> {
+        let digest = ContentDigest::from_content(b"somecontent");
+
+        let mut hasher = digest.hasher();
+        hasher.update(b"some");
+        hasher.update(b"content");
+        hasher.verify()?;
+
+        let mut hasher = digest.hasher();
+        hasher.update(b"some");
+        if hasher.verify().is_ok() {
+            panic!("expected verify to fail for partial content");
+        }
+        Ok(())
+    }
+
     #[test]
     fn try_verify_fails_with_different_content() -> Fallible<()> {
         let blob: &[u8] = b"somecontent";
         let different_blob: &[u8] = b"someothercontent";
-        let digest = DigestAlgorithm::Sha256.hash(&blob);
+        let digest = DigestAlgorithm::Sha256.hash(blob);
 
         if ContentDigest::try_new(digest)?
-            .try_verify(&different_blob)
+            .try_verify(different_blob)
             .is_ok()
         {
             panic!("expected try_verify to fail for a different blob");
@@ -0,0 +1,203 @@
+use crate::errors::Result;
+use crate::mediatypes::MediaTypes;
+use crate::v2::manifest::{ConfigBlob, Platform, PlatformSpec};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// OCI image manifest.
+///
+/// Specification is at https://github.com/opencontainers/image-spec/blob/main/manifest.md.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct OciManifestSpec {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u16,
+    #[serde(rename = "mediaType", skip_serializing_if = "Option::is_none")]
+    media_type: Option<String>,
+    #[serde(rename = "artifactType", skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    pub config: Descriptor,
+    pub layers: Vec<Descriptor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Descriptor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+/// Super-type for combining an OciManifestSpec with its image configuration.
+///
+/// Artifacts which are not container images do not carry an image
+/// configuration, in which case `config_blob` is `None`.
+#[derive(Debug, Default)]
+pub struct OciManifest {
+    pub manifest_spec: OciManifestSpec,
+    pub config_blob: Option<ConfigBlob>,
+}
+
+/// OCI image index.
+///
+/// Specification is at https://github.com/opencontainers/image-spec/blob/main/image-index.md.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct OciIndex {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u16,
+    #[serde(rename = "mediaType", skip_serializing_if = "Option::is_none")]
+    media_type: Option<String>,
+    #[serde(rename = "artifactType", skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    pub manifests: Vec<Descriptor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Descriptor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+/// Content descriptor, referencing a blob or a manifest by digest.
+///
+/// Specification is at https://github.com/opencontainers/image-spec/blob/main/descriptor.md.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Descriptor {
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urls: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    #[serde(rename = "artifactType", skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+/// Compression applied to the content of a layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Descriptor {
+    /// Parse the media type of this descriptor, if it is a known one.
+    pub fn known_media_type(&self) -> Option<MediaTypes> {
+        MediaTypes::from_str(&self.media_type).ok()
+    }
+
+    /// Compression of the layer content, if this descriptor is a known layer type.
+    pub fn compression(&self) -> Option<LayerCompression> {
+        match self.known_media_type()? {
+            MediaTypes::OciImageLayerTar | MediaTypes::OciImageLayerNondistributableTar => {
+                Some(LayerCompression::None)
+            }
+            MediaTypes::ImageLayerTgz
+            | MediaTypes::ForeignImageLayerTgz
+            | MediaTypes::OciImageLayerTgz
+            | MediaTypes::OciImageLayerNondistributableTgz => Some(LayerCompression::Gzip),
+            MediaTypes::OciImageLayerTzstd | MediaTypes::OciImageLayerNondistributableTzstd => {
+                Some(LayerCompression::Zstd)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether registries may store this content.
+    ///
+    /// Foreign and non-distributable layers are usually not pushed to
+    /// registries, but fetched from their `urls` instead.
+    pub fn is_distributable(&self) -> bool {
+        !matches!(
+            self.known_media_type(),
+            Some(MediaTypes::ForeignImageLayerTgz)
+                | Some(MediaTypes::OciImageLayerNondistributableTar)
+                | Some(MediaTypes::OciImageLayerNondistributableTgz)
+                | Some(MediaTypes::OciImageLayerNondistributableTzstd)
+        )
+    }
+}
+
+impl OciManifestSpec {
+    /// Get `Descriptor` of the configuration referenced by this manifest.
+    pub fn config(&self) -> &Descriptor {
+        &self.config
+    }
+
+    /// Fetch the image configuration for this manifest, if it references one.
+    pub(crate) async fn fetch_config_blob(
+        self,
+        client: crate::v2::Client,
+        repo: String,
+    ) -> Result<OciManifest> {
+        let config_blob = match MediaTypes::from_str(&self.config.media_type) {
+            Ok(MediaTypes::OciImageConfig) | Ok(MediaTypes::ContainerConfigV1) => Some(
+                super::manifest_schema2::fetch_config_blob(&client, &repo, &self.config.digest)
+                    .await?,
+            ),
+            _ => {
+                trace!(
+                    "Not fetching configuration with media type {}",
+                    self.config.media_type
+                );
+                None
+            }
+        };
+
+        Ok(OciManifest {
+            manifest_spec: self,
+            config_blob,
+        })
+    }
+}
+
+impl OciIndex {
+    /// Find the manifest best matching the given platform.
+    ///
+    /// Entries without a platform, such as artifacts, never match.
+    pub fn manifest_for_platform(&self, platform: &PlatformSpec) -> Option<&Descriptor> {
+        self.manifests
+            .iter()
+            .enumerate()
+            .filter_map(|(i, m)| {
+                let rank = platform.rank(m.platform.as_ref()?)?;
+                Some(((rank, i), m))
+            })
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, m)| m)
+    }
+
+    /// List the architectures of all manifests in this index which declare a platform.
+    pub fn architectures(&self) -> Vec<String> {
+        self.manifests
+            .iter()
+            .filter_map(|m| m.platform.as_ref())
+            .map(|p| p.architecture.clone())
+            .collect()
+    }
+}
+
+impl OciManifest {
+    /// List digests of all layers referenced by this manifest.
+    ///
+    /// The returned layers list is ordered starting with the base image first.
+    pub fn get_layers(&self) -> Vec<String> {
+        self.manifest_spec
+            .layers
+            .iter()
+            .map(|l| l.digest.clone())
+            .collect()
+    }
+
+    /// Get the architecture from the image configuration, if any.
+    pub fn architecture(&self) -> Option<String> {
+        self.config_blob
+            .as_ref()
+            .map(|c| c.architecture().to_owned())
+    }
+
+    /// Get manifest size
+    pub fn size(&self) -> u64 {
+        self.manifest_spec.layers.iter().map(|l| l.size).sum()
+    }
+}
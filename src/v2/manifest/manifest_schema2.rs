@@ -1,5 +1,6 @@
-use crate::errors::{Error, Result};
-use reqwest::Method;
+use crate::errors::Result;
+use crate::v2::manifest::{Descriptor, PlatformSpec};
+use std::collections::HashMap;
 
 /// Manifest version 2 schema 2.
 ///
@@ -11,7 +12,7 @@ pub struct ManifestSchema2Spec {
     #[serde(rename = "mediaType")]
     media_type: String,
     config: Config,
-    layers: Vec<S2Layer>,
+    pub layers: Vec<Descriptor>,
 }
 
 /// Super-type for combining a ManifestSchema2 with a ConfigBlob.
@@ -29,23 +30,146 @@ pub struct Config {
     pub digest: String,
 }
 
-/// Partial representation of a container image (application/vnd.docker.container.image.v1+json).
+/// Image configuration, either a Docker container image (application/vnd.docker.container.image.v1+json)
+/// or an OCI image configuration (application/vnd.oci.image.config.v1+json).
 ///
-/// The remaining fields according to [the image spec v1][image-spec-v1] are not covered.
+/// Fields are modeled after [the image spec v1][image-spec-v1] and [the OCI image spec][oci-config].
+/// `architecture` and `os` are required, Docker specific fields are only set for Docker images.
 ///
 /// [image-spec-v1]: https://github.com/moby/moby/blob/a30990b3c8d0d42280fa501287859e1d2393a951/image/spec/v1.md#image-json-description
+/// [oci-config]: https://github.com/opencontainers/image-spec/blob/main/config.md
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ConfigBlob {
-    architecture: String,
+    pub architecture: String,
+    pub os: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(rename = "os.version", skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    #[serde(rename = "os.features", skip_serializing_if = "Option::is_none")]
+    pub os_features: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<ContainerConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rootfs: Option<RootFs>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<History>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_config: Option<ContainerConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub docker_version: Option<String>,
+    #[serde(rename = "Size", skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
-struct S2Layer {
-    #[serde(rename = "mediaType")]
-    media_type: String,
-    size: u64,
-    digest: String,
-    urls: Option<Vec<String>>,
+/// Execution parameters used when running a container from the image.
+///
+/// Fields after `args_escaped` are Docker specific.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exposed_ports: Option<HashMap<String, serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmd: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<HashMap<String, serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_signal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub args_escaped: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domainname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attach_stdin: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attach_stdout: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attach_stderr: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tty: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_stdin: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdin_once: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_build: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub healthcheck: Option<HealthConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_disabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_timeout: Option<i64>,
+}
+
+/// Docker container health check, with durations in nanoseconds.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct HealthConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_period: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<i64>,
+}
+
+/// Layer content addresses of the uncompressed image filesystem.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RootFs {
+    #[serde(rename = "type")]
+    pub fs_type: String,
+    pub diff_ids: Vec<String>,
+}
+
+/// History of a single layer, ordered starting with the base image first.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct History {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub empty_layer: bool,
 }
 
 /// Manifest List.
@@ -69,15 +193,17 @@ pub struct ManifestObj {
 }
 
 /// Platform-related manifest entries.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Platform {
     pub architecture: String,
     pub os: String,
-    #[serde(rename = "os.version")]
+    #[serde(rename = "os.version", skip_serializing_if = "Option::is_none")]
     pub os_version: Option<String>,
-    #[serde(rename = "os.features")]
+    #[serde(rename = "os.features", skip_serializing_if = "Option::is_none")]
     pub os_features: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub variant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub features: Option<Vec<String>>,
 }
 
@@ -93,26 +219,7 @@ impl ManifestSchema2Spec {
         client: crate::v2::Client,
         repo: String,
     ) -> Result<ManifestSchema2> {
-        let url = {
-            let ep = format!(
-                "{}/v2/{}/blobs/{}",
-                client.base_url.clone(),
-                repo,
-                self.config.digest
-            );
-            reqwest::Url::parse(&ep)?
-        };
-
-        let r = client.build_reqwest(Method::GET, url.clone()).send().await?;
-
-        let status = r.status();
-        trace!("GET {:?}: {}", url, &status);
-
-        if !status.is_success() {
-            return Err(Error::UnexpectedHttpStatus(status));
-        }
-
-        let config_blob = r.json::<ConfigBlob>().await?;
+        let config_blob = fetch_config_blob(&client, &repo, &self.config.digest).await?;
 
         Ok(ManifestSchema2 {
             manifest_spec: self,
@@ -121,6 +228,52 @@ impl ManifestSchema2Spec {
     }
 }
 
+/// Fetch and deserialize the image configuration blob with the given digest.
+///
+/// The blob is verified against the digest before being deserialized.
+pub(crate) async fn fetch_config_blob(
+    client: &crate::v2::Client,
+    repo: &str,
+    digest: &str,
+) -> Result<ConfigBlob> {
+    let blob = client.get_blob(repo, digest).await?;
+    Ok(serde_json::from_slice::<ConfigBlob>(&blob)?)
+}
+
+impl ManifestList {
+    /// Find the manifest best matching the given platform.
+    ///
+    /// See `PlatformSpec::rank` for how platforms are compared.
+    pub fn manifest_for_platform(&self, platform: &PlatformSpec) -> Option<&ManifestObj> {
+        self.manifests
+            .iter()
+            .enumerate()
+            .filter_map(|(i, m)| platform.rank(&m.platform).map(|rank| ((rank, i), m)))
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, m)| m)
+    }
+
+    /// List the architectures of all manifests in this list.
+    pub fn architectures(&self) -> Vec<String> {
+        self.manifests
+            .iter()
+            .map(|m| m.platform.architecture.clone())
+            .collect()
+    }
+}
+
+impl ConfigBlob {
+    /// Get the architecture the image was built for.
+    pub fn architecture(&self) -> &str {
+        &self.architecture
+    }
+
+    /// Get the labels set on the image, if any.
+    pub fn labels(&self) -> Option<&HashMap<String, String>> {
+        self.config.as_ref()?.labels.as_ref()
+    }
+}
+
 impl ManifestSchema2 {
     /// List digests of all layers referenced by this manifest.
     ///
@@ -140,6 +293,6 @@ impl ManifestSchema2 {
         for l in self.manifest_spec.layers.iter() {
             result += l.size;
         }
-        return result;
+        result
     }
 }
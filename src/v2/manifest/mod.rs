@@ -1,8 +1,9 @@
 use crate::errors::{Error, Result};
 use crate::mediatypes;
-use crate::v2::*;
+use crate::v2::{registry_error, Client, ContentDigest, ContentDigestError};
 use mime;
-use reqwest::{self, header, StatusCode, Url};
+use reqwest::{self, header, Method, StatusCode, Url};
+use std::collections::HashMap;
 use std::iter::FromIterator;
 use std::str::FromStr;
 
@@ -14,11 +15,25 @@ mod manifest_schema2;
 
 pub use self::manifest_schema2::*;
 
+mod manifest_oci;
+
+pub use self::manifest_oci::*;
+
+mod platform;
+
+pub use self::platform::*;
+
 impl Client {
     /// Fetch an image manifest.
     ///
     /// The name and reference parameters identify the image.
     /// The reference may be either a tag or digest.
+    ///
+    /// Manifest lists and OCI indexes are accepted, so a multi-platform
+    /// reference returns the list itself instead of the manifest the
+    /// registry picks for its default platform. Layer methods fail on such
+    /// a manifest with `ManifestError::ManifestListUnresolved`; use
+    /// `get_manifest_for_platform` to resolve it first.
     pub async fn get_manifest(&self, name: &str, reference: &str) -> Result<Manifest> {
         self.get_manifest_and_ref(name, reference).await.map(|(manifest, _)| manifest)
     }
@@ -27,6 +42,7 @@ impl Client {
     ///
     /// The name and reference parameters identify the image.
     /// The reference may be either a tag or digest.
+    /// As with `get_manifest`, manifest lists and OCI indexes are returned as is.
     pub async fn get_manifest_and_ref(
         &self,
         name: &str,
@@ -38,14 +54,14 @@ impl Client {
 
         let client_spare0 = self.clone();
 
-        let res = self.build_reqwest(Method::GET, url.clone()).headers(accept_headers).send().await?;
+        let res = self.send_request(self.build_reqwest(Method::GET, url.clone()).headers(accept_headers)).await?;
 
         let status = res.status();
         trace!("GET '{}' status: {:?}", res.url(), status);
 
         match status {
             StatusCode::OK => {}
-            _ => return Err(Error::UnexpectedHttpStatus(status)),
+            _ => return Err(registry_error(res).await),
         }
 
         let headers = res.headers();
@@ -66,26 +82,75 @@ impl Client {
             media_type
         );
 
+        let body = res.bytes().await?;
+        // Manifests fetched by digest must match it. Signed schema1 manifests
+        // are excluded, as their digest does not cover the signatures.
+        if let Ok(digest) = ContentDigest::try_new(reference.to_string()) {
+            if media_type != mediatypes::MediaTypes::ManifestV2S1Signed {
+                digest.try_verify(&body)?;
+            }
+        }
+
         match media_type {
             mediatypes::MediaTypes::ManifestV2S1Signed => Ok((
-                res.json::<ManifestSchema1Signed>().await.map(Manifest::S1Signed)?,
+                serde_json::from_slice::<ManifestSchema1Signed>(&body).map(|m| Manifest::S1Signed(Box::new(m)))?,
                 content_digest,
             )),
             mediatypes::MediaTypes::ManifestV2S2 => {
-                let m = res.json::<ManifestSchema2Spec>().await?;
+                let m = serde_json::from_slice::<ManifestSchema2Spec>(&body)?;
                 Ok((
-                    m.fetch_config_blob(client_spare0, name.to_string()).await.map(Manifest::S2)?,
+                    m.fetch_config_blob(client_spare0, name.to_string()).await.map(|m| Manifest::S2(Box::new(m)))?,
                     content_digest,
                 ))
             }
             mediatypes::MediaTypes::ManifestList => Ok((
-                res.json::<ManifestList>().await.map(Manifest::ML)?,
+                serde_json::from_slice::<ManifestList>(&body).map(Manifest::ML)?,
+                content_digest,
+            )),
+            mediatypes::MediaTypes::OciImageManifest => {
+                let m = serde_json::from_slice::<OciManifestSpec>(&body)?;
+                Ok((
+                    m.fetch_config_blob(client_spare0, name.to_string()).await.map(|m| Manifest::Oci(Box::new(m)))?,
+                    content_digest,
+                ))
+            }
+            mediatypes::MediaTypes::OciImageIndex => Ok((
+                serde_json::from_slice::<OciIndex>(&body).map(|m| Manifest::OciIndex(Box::new(m)))?,
                 content_digest,
             )),
             unsupported => Err(Error::UnsupportedMediaType(unsupported)),
         }
     }
 
+    /// Fetch the image manifest for a specific platform.
+    ///
+    /// If the reference points to a manifest list or an OCI index, the
+    /// entry best matching `platform` is looked up and its manifest fetched
+    /// by digest. Single-platform manifests are returned as long as their
+    /// architecture is compatible.
+    pub async fn get_manifest_for_platform(
+        &self,
+        name: &str,
+        reference: &str,
+        platform: &PlatformSpec,
+    ) -> Result<Manifest> {
+        let manifest = self.get_manifest(name, reference).await?;
+
+        let digest = match manifest.digest_for_platform(platform)? {
+            Some(digest) => digest,
+            None => return Ok(manifest),
+        };
+
+        trace!("Resolved platform {} to manifest {}", platform, digest);
+
+        match self.get_manifest(name, &digest).await? {
+            Manifest::ML(_) | Manifest::OciIndex(_) => {
+                Err(ManifestError::NestedManifestList(digest).into())
+            }
+            child => Ok(child),
+        }
+    }
+
     fn build_url(&self, name: &str, reference: &str) -> Result<Url> {
         let ep = format!(
             "{}/v2/{}/manifests/{}",
@@ -93,7 +158,92 @@ impl Client {
             name,
             reference
         );
-        reqwest::Url::parse(&ep).map_err(|e| Error::from(e))
+        reqwest::Url::parse(&ep).map_err(Error::from)
+    }
+
+    /// Upload an image manifest.
+    ///
+    /// The name and reference parameters identify the image.
+    /// The reference may be either a tag or digest.
+    /// On success the manifest digest returned by the registry is returned.
+    ///
+    /// The manifest is re-serialized before upload, so fields which are not
+    /// modeled by `Manifest` are dropped and the digest may differ from the
+    /// one of the original content. Use `put_manifest_raw` to push the
+    /// original bytes unchanged.
+    pub async fn put_manifest(
+        &self,
+        name: &str,
+        reference: &str,
+        manifest: &Manifest,
+    ) -> Result<String> {
+        let (media_type, body) = manifest.to_payload()?;
+        self.put_manifest_raw(name, reference, media_type, &body).await
+    }
+
+    /// Upload an image manifest as raw bytes with the given media type.
+    ///
+    /// The content is sent unchanged, so its digest is preserved.
+    /// On success the manifest digest returned by the registry is returned.
+    pub async fn put_manifest_raw(
+        &self,
+        name: &str,
+        reference: &str,
+        media_type: mediatypes::MediaTypes,
+        body: &[u8],
+    ) -> Result<String> {
+        let url = self.build_url(name, reference)?;
+
+        let digest = ContentDigest::from_content(body);
+
+        let req = self
+            .build_reqwest(Method::PUT, url)
+            .header(header::CONTENT_TYPE, media_type.to_string())
+            .body(body.to_vec());
+        let res = self.send_request(req).await?;
+
+        let status = res.status();
+        trace!("PUT '{}' status: {:?}", res.url(), status);
+
+        match status {
+            StatusCode::CREATED => {}
+            _ => return Err(registry_error(res).await),
+        }
+
+        match res.headers().get("docker-content-digest") {
+            Some(content_digest_value) => {
+                let uploaded = ContentDigest::try_new(content_digest_value.to_str()?.to_string())?;
+                if uploaded != digest {
+                    return Err(ContentDigestError::Verify {
+                        expected: digest,
+                        got: uploaded,
+                    }
+                    .into());
+                }
+            }
+            None => debug!("cannot find manifestref in headers"),
+        };
+
+        Ok(digest.to_string())
+    }
+
+    /// Delete an image manifest.
+    ///
+    /// Registries only allow deleting manifests by digest, tags are rejected
+    /// before any request is made.
+    pub async fn delete_manifest(&self, name: &str, digest: &str) -> Result<()> {
+        let digest = ContentDigest::try_new(digest.to_string())?;
+        let url = self.build_url(name, &digest.to_string())?;
+
+        let res = self.send_request(self.build_reqwest(Method::DELETE, url)).await?;
+
+        let status = res.status();
+        trace!("DELETE '{}' status: {:?}", res.url(), status);
+
+        match status {
+            StatusCode::ACCEPTED => Ok(()),
+            _ => Err(registry_error(res).await),
+        }
     }
 
     /// Fetch content digest for a particular tag.
@@ -102,14 +252,14 @@ impl Client {
 
         let accept_headers = build_accept_headers(&self.index);
 
-        let res = self.build_reqwest(Method::HEAD, url).headers(accept_headers).send().await?;
+        let res = self.send_request(self.build_reqwest(Method::HEAD, url).headers(accept_headers)).await?;
 
         let status = res.status();
         trace!("HEAD '{}' status: {:?}", res.url(), status);
 
         match status {
             StatusCode::OK => {}
-            _ => return Err(Error::UnexpectedHttpStatus(status)),
+            _ => return Err(registry_error(res).await),
         }
 
         let headers = res.headers();
@@ -139,18 +289,18 @@ impl Client {
                 let m = mediatypes::MediaTypes::ManifestV2S2.to_mime();
                 vec![m]
             }
-            Some(ref v) => to_mimes(v),
+            Some(v) => to_mimes(v),
         };
 
         let mut accept_headers = header::HeaderMap::with_capacity(accept_types.len());
         for accept_type in accept_types {
-            let header_value = header::HeaderValue::from_str(&accept_type.to_string()).expect("mime type is always valid header value");
+            let header_value = header::HeaderValue::from_str(accept_type.as_ref()).expect("mime type is always valid header value");
             accept_headers.insert(header::ACCEPT, header_value);
         }
 
         trace!("HEAD {:?}", url);
 
-        let r = self.build_reqwest(Method::HEAD, url.clone()).headers(accept_headers).send().await.map_err(Error::from)?;
+        let r = self.send_request(self.build_reqwest(Method::HEAD, url.clone()).headers(accept_headers)).await?;
 
         let status = r.status();
 
@@ -162,12 +312,12 @@ impl Client {
 
         match status {
             StatusCode::MOVED_PERMANENTLY | StatusCode::TEMPORARY_REDIRECT | StatusCode::FOUND | StatusCode::OK => {
-                let media_type = evaluate_media_type(r.headers().get(header::CONTENT_TYPE), &r.url())?;
+                let media_type = evaluate_media_type(r.headers().get(header::CONTENT_TYPE), r.url())?;
                 trace!("Manifest media-type: {:?}", media_type);
                 Ok(Some(media_type))
             }
             StatusCode::NOT_FOUND => Ok(None),
-            _ => Err(Error::UnexpectedHttpStatus(status)),
+            _ => Err(registry_error(r).await),
         }
     }
 }
@@ -231,15 +381,16 @@ fn build_accept_headers(registry: &str) -> header::HeaderMap {
         // accept header types and their q value, as documented in
         // https://tools.ietf.org/html/rfc7231#section-5.3.2
         (mediatypes::MediaTypes::ManifestV2S2, 0.5),
+        (mediatypes::MediaTypes::OciImageManifest, 0.5),
+        (mediatypes::MediaTypes::OciImageIndex, 0.5),
         (mediatypes::MediaTypes::ManifestV2S1Signed, 0.4),
-        // TODO(steveeJ): uncomment this when all the Manifest methods work for it
-        // mediatypes::MediaTypes::ManifestList,
+        (mediatypes::MediaTypes::ManifestList, 0.5),
     ];
 
     let accepted_types_string = accepted_types.into_iter().map(|(ty, q)| {
         format!(
             "{}{}",
-            ty.to_string(),
+            ty,
             if no_q {
                 String::default()
             } else {
@@ -257,11 +408,17 @@ fn build_accept_headers(registry: &str) -> header::HeaderMap {
 }
 
 /// Umbrella type for common actions on the different manifest schema types
+///
+/// New manifest types may be added in future releases, matches on this
+/// enum must have a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Manifest {
-    S1Signed(manifest_schema1::ManifestSchema1Signed),
-    S2(manifest_schema2::ManifestSchema2),
+    S1Signed(Box<manifest_schema1::ManifestSchema1Signed>),
+    S2(Box<manifest_schema2::ManifestSchema2>),
     ML(manifest_schema2::ManifestList),
+    Oci(Box<manifest_oci::OciManifest>),
+    OciIndex(Box<manifest_oci::OciIndex>),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -272,10 +429,20 @@ pub enum ManifestError {
     ArchitectureMismatch,
     #[error("manifest {0} does not support the 'layer_digests' method")]
     LayerDigestsUnsupported(String),
+    #[error("manifest {0} does not support the 'layers' method")]
+    LayerDescriptorsUnsupported(String),
     #[error("manifest {0} does not support the 'size' method")]
     LayerSizeUnsupported(String),
     #[error("manifest {0} does not support the 'architecture' method")]
     ArchitectureNotSupported(String),
+    #[error("no manifest for platform {0}")]
+    PlatformNotFound(String),
+    #[error("manifest {0} is a manifest list and must be resolved to a platform first")]
+    ManifestListUnresolved(String),
+    #[error("manifest list entry {0} is itself a manifest list")]
+    NestedManifestList(String),
+    #[error("manifest {0} cannot be uploaded")]
+    UploadUnsupported(String),
 }
 
 impl Manifest {
@@ -286,6 +453,7 @@ impl Manifest {
         match (self, self.architectures(), architecture) {
             (Manifest::S1Signed(m), _, None) => Ok(m.get_layers()),
             (Manifest::S2(m), _, None) => Ok(m.get_layers()),
+            (Manifest::Oci(m), _, None) => Ok(m.get_layers()),
             (Manifest::S1Signed(m), Ok(ref self_architectures), Some(ref a)) => {
                 let self_a = self_architectures.first().ok_or(ManifestError::NoArchitecture)?;
                 if self_a != a {
@@ -300,27 +468,133 @@ impl Manifest {
                 }
                 Ok(m.get_layers())
             }
-            // Manifest::ML(_) => TODO(steveeJ),
+            (Manifest::Oci(m), Ok(ref self_architectures), Some(ref a)) => {
+                let self_a = self_architectures.first().ok_or(ManifestError::NoArchitecture)?;
+                if self_a != a {
+                    return Err(ManifestError::ArchitectureMismatch.into());
+                }
+                Ok(m.get_layers())
+            }
+            (Manifest::ML(_), _, _) | (Manifest::OciIndex(_), _, _) => {
+                Err(ManifestError::ManifestListUnresolved(format!("{:?}", self)).into())
+            }
             _ => Err(ManifestError::LayerDigestsUnsupported(format!("{:?}", self)).into()),
         }
     }
 
-    /// The architectures of the image the manifest points to, if available.
+    /// Descriptors of all layers referenced by this manifest, if available.
+    ///
+    /// The returned layers list is ordered starting with the base image first.
+    /// Schema1 manifests carry no layer descriptors, and manifest lists must
+    /// be resolved to a platform first.
+    pub fn layers(&self) -> Result<&[Descriptor]> {
+        match self {
+            Manifest::S2(m) => Ok(&m.manifest_spec.layers),
+            Manifest::Oci(m) => Ok(&m.manifest_spec.layers),
+            Manifest::ML(_) | Manifest::OciIndex(_) => {
+                Err(ManifestError::ManifestListUnresolved(format!("{:?}", self)).into())
+            }
+            Manifest::S1Signed(_) => {
+                Err(ManifestError::LayerDescriptorsUnsupported(format!("{:?}", self)).into())
+            }
+        }
+    }
+
+    /// The total download size of the image, summing the compressed sizes of its layers.
     pub fn download_size(&self) -> Result<u64> {
         match self {
             Manifest::S2(m) => Ok(m.size()),
-            // Manifest::ML(_) => TODO(steveeJ),
+            Manifest::Oci(m) => Ok(m.size()),
+            Manifest::ML(_) | Manifest::OciIndex(_) => {
+                Err(ManifestError::ManifestListUnresolved(format!("{:?}", self)).into())
+            }
             _ => Err(ManifestError::LayerSizeUnsupported(format!("{:?}", self)).into()),
         }
     }
 
+    /// Serialize this manifest for upload, returning its media type and content.
+    ///
+    /// Signed schema1 manifests cannot be re-serialized without breaking their signature.
+    fn to_payload(&self) -> Result<(mediatypes::MediaTypes, Vec<u8>)> {
+        match self {
+            Manifest::S2(m) => Ok((
+                mediatypes::MediaTypes::ManifestV2S2,
+                serde_json::to_vec(&m.manifest_spec)?,
+            )),
+            Manifest::ML(m) => Ok((
+                mediatypes::MediaTypes::ManifestList,
+                serde_json::to_vec(m)?,
+            )),
+            Manifest::Oci(m) => Ok((
+                mediatypes::MediaTypes::OciImageManifest,
+                serde_json::to_vec(&m.manifest_spec)?,
+            )),
+            Manifest::OciIndex(m) => Ok((
+                mediatypes::MediaTypes::OciImageIndex,
+                serde_json::to_vec(m)?,
+            )),
+            Manifest::S1Signed(_) => Err(ManifestError::UploadUnsupported(
+                mediatypes::MediaTypes::ManifestV2S1Signed.to_string(),
+            )
+            .into()),
+        }
+    }
+
     /// The architectures of the image the manifest points to, if available.
     pub fn architectures(&self) -> Result<Vec<String>> {
         match self {
             Manifest::S1Signed(m) => Ok([m.architecture.clone()].to_vec()),
             Manifest::S2(m) => Ok([m.architecture()].to_vec()),
-            // Manifest::ML(_) => TODO(steveeJ),
-            _ => Err(ManifestError::ArchitectureNotSupported(format!("{:?}", self)).into()),
+            Manifest::Oci(m) => Ok(m.architecture().into_iter().collect()),
+            Manifest::ML(m) => Ok(m.architectures()),
+            Manifest::OciIndex(m) => Ok(m.architectures()),
+        }
+    }
+
+    /// The labels of the image the manifest points to, if available.
+    ///
+    /// For schema1 manifests, the labels are taken from the topmost layer.
+    pub fn labels(&self) -> Option<HashMap<String, String>> {
+        match self {
+            Manifest::S1Signed(m) => m.get_labels(0),
+            Manifest::S2(m) => m.config_blob.labels().cloned(),
+            Manifest::Oci(m) => m.config_blob.as_ref()?.labels().cloned(),
+            Manifest::ML(_) | Manifest::OciIndex(_) => None,
+        }
+    }
+
+    /// The platforms listed by a manifest list or OCI index.
+    ///
+    /// Single-platform manifests return an empty list.
+    pub fn platforms(&self) -> Vec<&Platform> {
+        match self {
+            Manifest::ML(m) => m.manifests.iter().map(|m| &m.platform).collect(),
+            Manifest::OciIndex(m) => m.manifests.iter().filter_map(|m| m.platform.as_ref()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Resolve a manifest list or OCI index to the digest of the manifest for `platform`.
+    ///
+    /// Returns `None` for single-platform manifests whose architecture,
+    /// if known, is compatible with the requested one.
+    pub fn digest_for_platform(&self, platform: &PlatformSpec) -> Result<Option<String>> {
+        let not_found = || ManifestError::PlatformNotFound(platform.to_string());
+        match self {
+            Manifest::ML(m) => m
+                .manifest_for_platform(platform)
+                .map(|m| Some(m.digest.clone()))
+                .ok_or_else(|| not_found().into()),
+            Manifest::OciIndex(m) => m
+                .manifest_for_platform(platform)
+                .map(|m| Some(m.digest.clone()))
+                .ok_or_else(|| not_found().into()),
+            _ => match self.architectures()?.first() {
+                Some(a) if !platform.matches_architecture(a) => {
+                    Err(ManifestError::ArchitectureMismatch.into())
+                }
+                _ => Ok(None),
+            },
         }
     }
 }
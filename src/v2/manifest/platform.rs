@@ -0,0 +1,425 @@
+//! Platform specifiers for selecting manifests from manifest lists and OCI indexes.
+//!
+//! Normalization and matching follow containerd's `platforms` package,
+//! see https://github.com/containerd/platforms.
+
+use crate::v2::manifest::Platform;
+use std::{fmt, str};
+
+/// Platform requested when resolving a manifest list or an OCI index.
+///
+/// All fields are normalized on construction, so that e.g. `aarch64` and
+/// `arm64/v8` compare equal. Besides exact matches, compatible platforms
+/// are accepted too and ranked by preference, e.g. `linux/arm64` falls
+/// back to `linux/arm/v8` down to `linux/arm/v5`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlatformSpec {
+    os: String,
+    architecture: String,
+    variant: Option<String>,
+    os_version: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PlatformParseError {
+    #[error("invalid platform component {0:?}")]
+    InvalidComponent(String),
+    #[error("unknown operating system or architecture {0:?}")]
+    UnknownComponent(String),
+    #[error("too many components in platform specifier {0:?}")]
+    TooManyComponents(String),
+}
+
+static KNOWN_OS: &[&str] = &[
+    "aix",
+    "android",
+    "darwin",
+    "dragonfly",
+    "freebsd",
+    "illumos",
+    "ios",
+    "js",
+    "linux",
+    "netbsd",
+    "openbsd",
+    "plan9",
+    "solaris",
+    "windows",
+];
+
+static KNOWN_ARCH: &[&str] = &[
+    "386", "amd64", "arm", "arm64", "loong64", "mips", "mips64", "mips64le", "mipsle", "ppc64",
+    "ppc64le", "riscv64", "s390x", "wasm",
+];
+
+impl PlatformSpec {
+    /// Create a normalized platform specifier.
+    pub fn new(os: &str, architecture: &str, variant: Option<&str>) -> Self {
+        let (architecture, variant) = normalize_arch(architecture, variant.unwrap_or_default());
+        PlatformSpec {
+            os: normalize_os(os),
+            architecture,
+            variant,
+            os_version: None,
+        }
+    }
+
+    /// Platform of the running host.
+    pub fn host() -> Self {
+        let arch = match std::env::consts::ARCH {
+            "x86" => "386",
+            "powerpc64" if cfg!(target_endian = "little") => "ppc64le",
+            "powerpc64" => "ppc64",
+            "mips" if cfg!(target_endian = "little") => "mipsle",
+            "mips64" if cfg!(target_endian = "little") => "mips64le",
+            "loongarch64" => "loong64",
+            arch => arch,
+        };
+        PlatformSpec::new(std::env::consts::OS, arch, None)
+    }
+
+    /// Restrict matches to the given OS version.
+    ///
+    /// This is only relevant for Windows, where images must match the
+    /// major, minor and build number of the host.
+    pub fn with_os_version(mut self, os_version: &str) -> Self {
+        self.os_version = Some(os_version.to_string());
+        self
+    }
+
+    pub fn os(&self) -> &str {
+        &self.os
+    }
+
+    pub fn architecture(&self) -> &str {
+        &self.architecture
+    }
+
+    pub fn variant(&self) -> Option<&str> {
+        self.variant.as_deref()
+    }
+
+    pub fn os_version(&self) -> Option<&str> {
+        self.os_version.as_deref()
+    }
+
+    /// Rank a platform against this specifier.
+    ///
+    /// Returns `None` if the platform is not compatible, otherwise lower
+    /// values are better matches with `0` being an exact match.
+    pub fn rank(&self, platform: &Platform) -> Option<usize> {
+        let candidate = PlatformSpec::from(platform);
+        if candidate.os != self.os || !self.os_version_matches(&candidate) {
+            return None;
+        }
+        self.compatible().iter().position(|(arch, variant)| {
+            *arch == candidate.architecture && *variant == candidate.variant
+        })
+    }
+
+    /// Check whether a platform is compatible with this specifier.
+    pub fn matches(&self, platform: &Platform) -> bool {
+        self.rank(platform).is_some()
+    }
+
+    /// Check whether an image for `architecture` can run on this platform.
+    ///
+    /// Used for single-platform manifests, where only the architecture is known.
+    pub fn matches_architecture(&self, architecture: &str) -> bool {
+        let (architecture, _) = normalize_arch(architecture, "");
+        self.compatible()
+            .iter()
+            .any(|(arch, _)| *arch == architecture)
+    }
+
+    fn os_version_matches(&self, candidate: &PlatformSpec) -> bool {
+        if self.os != "windows" {
+            return true;
+        }
+        match (&self.os_version, &candidate.os_version) {
+            (Some(wanted), Some(got)) => windows_build(wanted) == windows_build(got),
+            _ => true,
+        }
+    }
+
+    /// Architecture and variant pairs compatible with this specifier, best match first.
+    fn compatible(&self) -> Vec<(String, Option<String>)> {
+        let mut vector = vec![(self.architecture.clone(), self.variant.clone())];
+        match self.architecture.as_str() {
+            "amd64" => {
+                let level = self
+                    .variant
+                    .as_deref()
+                    .and_then(variant_number)
+                    .unwrap_or(1);
+                for l in (2..level).rev() {
+                    vector.push(("amd64".to_string(), Some(format!("v{}", l))));
+                }
+                if level > 1 {
+                    vector.push(("amd64".to_string(), None));
+                }
+                vector.push(("386".to_string(), None));
+            }
+            "arm64" => {
+                if self.variant.is_some() {
+                    vector.push(("arm64".to_string(), None));
+                }
+                vector.extend(arm_variants(8));
+            }
+            "arm" => {
+                let level = self
+                    .variant
+                    .as_deref()
+                    .and_then(variant_number)
+                    .unwrap_or(7);
+                vector.extend(arm_variants(level).into_iter().skip(1));
+            }
+            _ => {}
+        }
+        vector
+    }
+}
+
+impl Default for PlatformSpec {
+    fn default() -> Self {
+        PlatformSpec::host()
+    }
+}
+
+impl From<&Platform> for PlatformSpec {
+    fn from(platform: &Platform) -> Self {
+        let mut spec = PlatformSpec::new(
+            &platform.os,
+            &platform.architecture,
+            platform.variant.as_deref(),
+        );
+        spec.os_version = platform.os_version.clone();
+        spec
+    }
+}
+
+impl str::FromStr for PlatformSpec {
+    type Err = PlatformParseError;
+
+    /// Parse a specifier of the form `os[/arch[/variant]]`.
+    ///
+    /// A single component may also name an architecture, in which case the
+    /// host OS is assumed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('/').collect();
+        for part in &parts {
+            let valid = !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+            if !valid {
+                return Err(PlatformParseError::InvalidComponent(s.to_string()));
+            }
+        }
+
+        match parts.as_slice() {
+            [single] => {
+                let os = normalize_os(single);
+                if KNOWN_OS.contains(&os.as_str()) {
+                    let host = PlatformSpec::host();
+                    return Ok(PlatformSpec::new(&os, &host.architecture, host.variant()));
+                }
+                let (arch, variant) = normalize_arch(single, "");
+                if KNOWN_ARCH.contains(&arch.as_str()) {
+                    return Ok(PlatformSpec::new(
+                        std::env::consts::OS,
+                        &arch,
+                        variant.as_deref(),
+                    ));
+                }
+                Err(PlatformParseError::UnknownComponent(s.to_string()))
+            }
+            [os, arch] => Ok(PlatformSpec::new(os, arch, None)),
+            [os, arch, variant] => Ok(PlatformSpec::new(os, arch, Some(variant))),
+            _ => Err(PlatformParseError::TooManyComponents(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for PlatformSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(ref variant) = self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+fn normalize_os(os: &str) -> String {
+    match os.to_lowercase().as_str() {
+        "macos" => "darwin".to_string(),
+        os => os.to_string(),
+    }
+}
+
+fn normalize_arch(arch: &str, variant: &str) -> (String, Option<String>) {
+    let arch = arch.to_lowercase();
+    let variant = variant.to_lowercase();
+    let (arch, variant) = match arch.as_str() {
+        "i386" => ("386", ""),
+        "x86_64" | "x86-64" | "amd64" => match variant.as_str() {
+            "v1" => ("amd64", ""),
+            _ => ("amd64", variant.as_str()),
+        },
+        "aarch64" | "arm64" => match variant.as_str() {
+            "8" | "v8" | "v8.0" => ("arm64", ""),
+            "9" | "9.0" | "v9.0" => ("arm64", "v9"),
+            _ => ("arm64", variant.as_str()),
+        },
+        "armhf" => ("arm", "v7"),
+        "armel" => ("arm", "v6"),
+        "arm" => match variant.as_str() {
+            "" | "7" => ("arm", "v7"),
+            "5" => ("arm", "v5"),
+            "6" => ("arm", "v6"),
+            "8" => ("arm", "v8"),
+            _ => ("arm", variant.as_str()),
+        },
+        _ => (arch.as_str(), variant.as_str()),
+    };
+    let variant = if variant.is_empty() {
+        None
+    } else {
+        Some(variant.to_string())
+    };
+    (arch.to_string(), variant)
+}
+
+fn variant_number(variant: &str) -> Option<u8> {
+    variant.trim_start_matches('v').parse().ok()
+}
+
+/// 32 bit arm variants from `from` down to `v5`.
+fn arm_variants(from: u8) -> Vec<(String, Option<String>)> {
+    (5..=from)
+        .rev()
+        .map(|v| ("arm".to_string(), Some(format!("v{}", v))))
+        .collect()
+}
+
+/// Major, minor and build number of a Windows OS version.
+fn windows_build(os_version: &str) -> Vec<&str> {
+    os_version.split('.').take(3).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn platform(os: &str, architecture: &str, variant: Option<&str>) -> Platform {
+        Platform {
+            os: os.to_string(),
+            architecture: architecture.to_string(),
+            variant: variant.map(ToString::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_and_normalize() -> Result<(), PlatformParseError> {
+        let cases = vec![
+            ("linux/amd64", "linux/amd64"),
+            ("linux/x86_64", "linux/amd64"),
+            ("Linux/AMD64/v1", "linux/amd64"),
+            ("linux/aarch64", "linux/arm64"),
+            ("linux/arm64/v8", "linux/arm64"),
+            ("linux/arm64/9", "linux/arm64/v9"),
+            ("linux/arm", "linux/arm/v7"),
+            ("linux/armhf", "linux/arm/v7"),
+            ("linux/armel", "linux/arm/v6"),
+            ("linux/arm/6", "linux/arm/v6"),
+            ("linux/i386", "linux/386"),
+            ("macos/arm64", "darwin/arm64"),
+            ("windows/amd64", "windows/amd64"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                expected,
+                PlatformSpec::from_str(input)?.to_string(),
+                "{}",
+                input
+            );
+        }
+
+        let spec = PlatformSpec::from_str("aarch64")?;
+        assert_eq!(std::env::consts::OS, spec.os());
+        assert_eq!("arm64", spec.architecture());
+        assert_eq!("linux", PlatformSpec::from_str("linux")?.os());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for input in &[
+            "",
+            "linux/",
+            "linux//v7",
+            "linux/amd64/v1/extra",
+            "unknown",
+            "linux/am d64",
+        ] {
+            assert!(PlatformSpec::from_str(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn rank_arm_fallback() -> Result<(), PlatformParseError> {
+        let arm64 = PlatformSpec::from_str("linux/arm64")?;
+        assert_eq!(Some(0), arm64.rank(&platform("linux", "arm64", Some("v8"))));
+        assert_eq!(Some(0), arm64.rank(&platform("linux", "aarch64", None)));
+        assert_eq!(Some(1), arm64.rank(&platform("linux", "arm", Some("v8"))));
+        assert_eq!(Some(2), arm64.rank(&platform("linux", "arm", None)));
+        assert_eq!(Some(4), arm64.rank(&platform("linux", "arm", Some("v5"))));
+        assert_eq!(None, arm64.rank(&platform("linux", "amd64", None)));
+        assert_eq!(None, arm64.rank(&platform("windows", "arm64", None)));
+
+        let armv6 = PlatformSpec::from_str("linux/arm/v6")?;
+        assert!(armv6.matches(&platform("linux", "arm", Some("v5"))));
+        assert!(!armv6.matches(&platform("linux", "arm", Some("v7"))));
+        assert!(!armv6.matches(&platform("linux", "arm", None)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rank_amd64_levels() -> Result<(), PlatformParseError> {
+        let v3 = PlatformSpec::from_str("linux/amd64/v3")?;
+        assert_eq!(Some(0), v3.rank(&platform("linux", "amd64", Some("v3"))));
+        assert_eq!(Some(1), v3.rank(&platform("linux", "amd64", Some("v2"))));
+        assert_eq!(Some(2), v3.rank(&platform("linux", "amd64", None)));
+        assert_eq!(Some(3), v3.rank(&platform("linux", "386", None)));
+        assert_eq!(None, v3.rank(&platform("linux", "amd64", Some("v4"))));
+
+        let amd64 = PlatformSpec::from_str("linux/amd64")?;
+        assert_eq!(Some(1), amd64.rank(&platform("linux", "i386", None)));
+        assert!(amd64.matches_architecture("x86_64"));
+        assert!(!amd64.matches_architecture("arm64"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn windows_os_version() -> Result<(), PlatformParseError> {
+        let spec = PlatformSpec::from_str("windows/amd64")?.with_os_version("10.0.17763.1234");
+
+        let mut same_build = platform("windows", "amd64", None);
+        same_build.os_version = Some("10.0.17763.5678".to_string());
+        assert!(spec.matches(&same_build));
+
+        let mut other_build = platform("windows", "amd64", None);
+        other_build.os_version = Some("10.0.20348.1".to_string());
+        assert!(!spec.matches(&other_build));
+
+        assert!(spec.matches(&platform("windows", "amd64", None)));
+
+        Ok(())
+    }
+}
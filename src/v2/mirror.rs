@@ -0,0 +1,175 @@
+use crate::errors::Result;
+use crate::v2::*;
+use reqwest::header;
+use std::time::Duration;
+
+/// Default time allowed to connect to a mirror before skipping it.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Mirror of the registry, tried before it for pulls.
+///
+/// The location is a host, optionally followed by a namespace which
+/// prefixes repository names on the mirror. For example, with the location
+/// `mirror.example.com/dockerhub`, the repository `library/busybox` is
+/// pulled from `mirror.example.com/dockerhub/library/busybox`, as in
+/// `registries.conf`.
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    location: String,
+    insecure: bool,
+    accept_invalid_certs: bool,
+    connect_timeout: Duration,
+}
+
+impl Mirror {
+    pub fn new(location: &str) -> Self {
+        Mirror {
+            location: location.trim_end_matches('/').to_owned(),
+            insecure: false,
+            accept_invalid_certs: false,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+        }
+    }
+
+    /// Whether to use an insecure HTTP connection to the mirror.
+    pub fn insecure(mut self, insecure: bool) -> Self {
+        self.insecure = insecure;
+        self
+    }
+
+    /// Set whether or not to accept invalid certificates from the mirror.
+    pub fn accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+
+    /// Set the time allowed to connect to the mirror, 5 seconds by default.
+    ///
+    /// A mirror which cannot be reached in time is skipped.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub(crate) fn build(self) -> Result<MirrorEndpoint> {
+        let mut parts = self.location.splitn(2, '/');
+        let host = parts.next().unwrap_or_default().to_owned();
+        let namespace = parts.next().map(str::to_owned);
+        let scheme = if self.insecure { "http" } else { "https" };
+
+        let client = reqwest::ClientBuilder::new()
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+            .connect_timeout(self.connect_timeout)
+            .build()?;
+        let blob_client = reqwest::ClientBuilder::new()
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+            .connect_timeout(self.connect_timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        Ok(MirrorEndpoint {
+            base_url: format!("{}://{}", scheme, host),
+            namespace,
+            client,
+            blob_client,
+        })
+    }
+}
+
+/// A `Mirror` with its HTTP clients.
+#[derive(Clone, Debug)]
+pub(crate) struct MirrorEndpoint {
+    base_url: String,
+    namespace: Option<String>,
+    client: reqwest::Client,
+    blob_client: reqwest::Client,
+}
+
+impl MirrorEndpoint {
+    /// Map the path of a registry request to this mirror.
+    fn url(&self, path: &str, query: Option<&str>) -> Result<Url> {
+        let path = path.trim_start_matches("/v2/");
+        let mut url = match &self.namespace {
+            Some(namespace) => Url::parse(&format!("{}/v2/{}/{}", self.base_url, namespace, path))?,
+            None => Url::parse(&format!("{}/v2/{}", self.base_url, path))?,
+        };
+        url.set_query(query);
+        Ok(url)
+    }
+}
+
+impl Client {
+    /// Try to answer a pull request from the mirrors, in order.
+    ///
+    /// Only `GET` requests for manifests and blobs are mirrored; existence
+    /// checks with `HEAD` report the state of the registry itself, which a
+    /// pull-through cache may not share. A mirror is skipped if it
+    /// cannot be reached or answers with an error, e.g. because it does not
+    /// have the content or requires authentication, or if it ignores the
+    /// `Range` of a partial request; `None` is returned if
+    /// all of them were skipped, and the registry itself should be asked.
+    /// Mirrors are accessed without the registry credentials.
+    pub(crate) async fn send_to_mirrors(
+        &self,
+        request: &reqwest::Request,
+        no_redirects: bool,
+    ) -> Result<Option<reqwest::Response>> {
+        if self.mirrors.is_empty() || !is_mirrored(request, &self.base_url)? {
+            return Ok(None);
+        }
+
+        let ranged = request.headers().contains_key(header::RANGE);
+
+        for mirror in &self.mirrors {
+            let mut mirror_request = match request.try_clone() {
+                Some(mirror_request) => mirror_request,
+                None => return Ok(None),
+            };
+            *mirror_request.url_mut() = mirror.url(request.url().path(), request.url().query())?;
+            mirror_request.headers_mut().remove(header::AUTHORIZATION);
+
+            let client = if no_redirects {
+                &mirror.blob_client
+            } else {
+                &mirror.client
+            };
+            trace!(
+                "{} {} on mirror",
+                mirror_request.method(),
+                mirror_request.url()
+            );
+            match client.execute(mirror_request).await {
+                Ok(res) if is_usable(res.status(), ranged) => return Ok(Some(res)),
+                Ok(res) => trace!("Mirror answered {} for {}", res.status(), res.url()),
+                Err(e) => trace!("Mirror {} failed: {}", mirror.base_url, e),
+            }
+        }
+
+        trace!("Falling back to {}", self.base_url);
+        Ok(None)
+    }
+}
+
+/// Whether a mirror response with `status` can be returned to the caller.
+///
+/// Blob redirects to a storage backend are followed by the caller.
+fn is_usable(status: StatusCode, ranged: bool) -> bool {
+    if ranged {
+        status == StatusCode::PARTIAL_CONTENT || status.is_redirection()
+    } else {
+        status.is_success() || status.is_redirection()
+    }
+}
+
+/// Whether the request fetches a manifest or a blob from the registry.
+fn is_mirrored(request: &reqwest::Request, base_url: &str) -> Result<bool> {
+    lazy_static! {
+        static ref CONTENT_PATH: regex::Regex =
+            regex::Regex::new(r"^/v2/.+/(manifests|blobs)/[^/]+$")
+                .expect("this static regex is valid");
+    }
+
+    Ok(*request.method() == Method::GET
+        && request.url().origin() == Url::parse(base_url)?.origin()
+        && CONTENT_PATH.is_match(request.url().path()))
+}
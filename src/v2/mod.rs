@@ -2,7 +2,8 @@
 //!
 //! This module provides a `Client` which can be used to list
 //! images and tags, to check for the presence of blobs (manifests,
-//! layers and other objects) by digest, and to retrieve them.
+//! layers and other objects) by digest, to retrieve them and to
+//! upload new blobs.
 //!
 //! ## Example
 //!
@@ -30,14 +31,21 @@
 use crate::errors::*;
 use futures::prelude::*;
 use reqwest::{Method, StatusCode, Url};
+use std::sync::{Arc, Mutex};
 
 mod config;
 pub use self::config::Config;
 
+mod mirror;
+pub use self::mirror::Mirror;
+
 mod catalog;
 
 mod auth;
-pub use auth::WwwHeaderParseError;
+pub use auth::{
+    mount_scopes, repository_scope, Auth, AuthSession, BasicAuth, BearerAuth, Masked,
+    SessionToken, WwwHeaderParseError,
+};
 
 pub mod manifest;
 
@@ -45,12 +53,15 @@ mod tags;
 
 mod blobs;
 
+mod blobs_upload;
+pub use self::blobs_upload::{BlobMount, BlobUpload};
+
 mod content_digest;
 pub(crate) use self::content_digest::ContentDigest;
 pub use self::content_digest::ContentDigestError;
 
 /// A Client to make outgoing API requests to a registry.
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Client {
     base_url: String,
     credentials: Option<(String, String)>,
@@ -58,6 +69,28 @@ pub struct Client {
     user_agent: Option<String>,
     auth: Option<auth::Auth>,
     client: reqwest::Client,
+    blob_client: reqwest::Client,
+    force_oauth: bool,
+    anonymous_first: bool,
+    credential_provider: Option<Arc<dyn crate::credentials::CredentialProvider>>,
+    mirrors: Vec<mirror::MirrorEndpoint>,
+    tokens: Arc<Mutex<auth::TokenCache>>,
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("base_url", &self.base_url)
+            .field("credentials", &self.credentials.as_ref().map(|(user, _)| (user, "***")))
+            .field("index", &self.index)
+            .field("user_agent", &self.user_agent)
+            .field("auth", &self.auth)
+            .field("force_oauth", &self.force_oauth)
+            .field("anonymous_first", &self.anonymous_first)
+            .field("credential_provider", &self.credential_provider)
+            .field("mirrors", &self.mirrors)
+            .finish()
+    }
 }
 
 impl Client {
@@ -78,7 +111,9 @@ impl Client {
     pub async fn is_v2_supported(&self) -> Result<bool> {
         match self.is_v2_supported_and_authorized().await {
             Ok((v2_supported, _)) => Ok(v2_supported),
-            Err(crate::Error::UnexpectedHttpStatus(_)) => Ok(false),
+            Err(crate::Error::UnexpectedHttpStatus(_)) | Err(crate::Error::Registry { .. }) => {
+                Ok(false)
+            }
             Err(e) => Err(e),
         }
     }
@@ -103,7 +138,7 @@ impl Client {
             (StatusCode::UNAUTHORIZED, Some(x)) => Ok((x == api_version, false)),
             (s, v) => {
                 trace!("Got unexpected status {}, header version {:?}", s, v);
-                return Err(crate::Error::UnexpectedHttpStatus(s));
+                return Err(registry_error(response).await);
             }
         };
 
@@ -112,9 +147,26 @@ impl Client {
 
     /// Takes reqwest's async RequestBuilder and injects an authentication header if a token is present
     fn build_reqwest(&self, method: Method, url: Url) -> reqwest::RequestBuilder {
-        let mut builder = self.client.request(method, url);
+        self.build_reqwest_with(&self.client, method, url, true)
+    }
 
-        if let Some(auth) = &self.auth {
+    /// Like `build_reqwest`, but on a client which does not follow redirects.
+    ///
+    /// The authentication header is only injected if `with_auth` is set.
+    fn build_blob_reqwest(&self, method: Method, url: Url, with_auth: bool) -> reqwest::RequestBuilder {
+        self.build_reqwest_with(&self.blob_client, method, url, with_auth)
+    }
+
+    fn build_reqwest_with(
+        &self,
+        client: &reqwest::Client,
+        method: Method,
+        url: Url,
+        with_auth: bool,
+    ) -> reqwest::RequestBuilder {
+        let mut builder = client.request(method, url);
+
+        if let (true, Some(auth)) = (with_auth, &self.auth) {
             builder = auth.add_auth_headers(builder);
         };
 
@@ -126,14 +178,200 @@ impl Client {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
-struct ApiError {
-    code: String,
-    message: String,
-    detail: String,
+/// Error reported by the registry in the body of a failed response.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ApiError {
+    pub code: ApiErrorCode,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<serde_json::Value>,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+/// Error codes reported by the registry.
+///
+/// Codes are documented at https://docs.docker.com/registry/spec/api/#errors-2.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum ApiErrorCode {
+    BlobUnknown,
+    BlobUploadInvalid,
+    BlobUploadUnknown,
+    DigestInvalid,
+    ManifestBlobUnknown,
+    ManifestInvalid,
+    ManifestUnknown,
+    ManifestUnverified,
+    NameInvalid,
+    NameUnknown,
+    PaginationNumberInvalid,
+    RangeInvalid,
+    SizeInvalid,
+    TagInvalid,
+    Unauthorized,
+    Denied,
+    Unsupported,
+    TooManyRequests,
+    /// Any code not covered by the specification.
+    Other(String),
+}
+
+impl ApiErrorCode {
+    /// The code as it appears on the wire, e.g. `BLOB_UNKNOWN`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ApiErrorCode::BlobUnknown => "BLOB_UNKNOWN",
+            ApiErrorCode::BlobUploadInvalid => "BLOB_UPLOAD_INVALID",
+            ApiErrorCode::BlobUploadUnknown => "BLOB_UPLOAD_UNKNOWN",
+            ApiErrorCode::DigestInvalid => "DIGEST_INVALID",
+            ApiErrorCode::ManifestBlobUnknown => "MANIFEST_BLOB_UNKNOWN",
+            ApiErrorCode::ManifestInvalid => "MANIFEST_INVALID",
+            ApiErrorCode::ManifestUnknown => "MANIFEST_UNKNOWN",
+            ApiErrorCode::ManifestUnverified => "MANIFEST_UNVERIFIED",
+            ApiErrorCode::NameInvalid => "NAME_INVALID",
+            ApiErrorCode::NameUnknown => "NAME_UNKNOWN",
+            ApiErrorCode::PaginationNumberInvalid => "PAGINATION_NUMBER_INVALID",
+            ApiErrorCode::RangeInvalid => "RANGE_INVALID",
+            ApiErrorCode::SizeInvalid => "SIZE_INVALID",
+            ApiErrorCode::TagInvalid => "TAG_INVALID",
+            ApiErrorCode::Unauthorized => "UNAUTHORIZED",
+            ApiErrorCode::Denied => "DENIED",
+            ApiErrorCode::Unsupported => "UNSUPPORTED",
+            ApiErrorCode::TooManyRequests => "TOOMANYREQUESTS",
+            ApiErrorCode::Other(code) => code,
+        }
+    }
+}
+
+impl From<String> for ApiErrorCode {
+    fn from(code: String) -> Self {
+        match code.as_str() {
+            "BLOB_UNKNOWN" => ApiErrorCode::BlobUnknown,
+            "BLOB_UPLOAD_INVALID" => ApiErrorCode::BlobUploadInvalid,
+            "BLOB_UPLOAD_UNKNOWN" => ApiErrorCode::BlobUploadUnknown,
+            "DIGEST_INVALID" => ApiErrorCode::DigestInvalid,
+            "MANIFEST_BLOB_UNKNOWN" => ApiErrorCode::ManifestBlobUnknown,
+            "MANIFEST_INVALID" => ApiErrorCode::ManifestInvalid,
+            "MANIFEST_UNKNOWN" => ApiErrorCode::ManifestUnknown,
+            "MANIFEST_UNVERIFIED" => ApiErrorCode::ManifestUnverified,
+            "NAME_INVALID" => ApiErrorCode::NameInvalid,
+            "NAME_UNKNOWN" => ApiErrorCode::NameUnknown,
+            "PAGINATION_NUMBER_INVALID" => ApiErrorCode::PaginationNumberInvalid,
+            "RANGE_INVALID" => ApiErrorCode::RangeInvalid,
+            "SIZE_INVALID" => ApiErrorCode::SizeInvalid,
+            "TAG_INVALID" => ApiErrorCode::TagInvalid,
+            "UNAUTHORIZED" => ApiErrorCode::Unauthorized,
+            "DENIED" => ApiErrorCode::Denied,
+            "UNSUPPORTED" => ApiErrorCode::Unsupported,
+            "TOOMANYREQUESTS" => ApiErrorCode::TooManyRequests,
+            _ => ApiErrorCode::Other(code),
+        }
+    }
+}
+
+impl From<ApiErrorCode> for String {
+    fn from(code: ApiErrorCode) -> Self {
+        code.as_str().to_string()
+    }
+}
+
+impl std::fmt::Display for ApiErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
+/// Error envelope sent by the registry in the body of a failed response.
 #[derive(Debug, Default, Deserialize, Serialize)]
 struct Errors {
     errors: Vec<ApiError>,
 }
+
+/// Turn an unsuccessful response into an error.
+///
+/// The registry error envelope is decoded from the body when present,
+/// otherwise client errors carry the raw body.
+pub(crate) async fn registry_error(res: reqwest::Response) -> Error {
+    let status = res.status();
+    let body = match res.bytes().await {
+        Ok(body) => body,
+        Err(e) => return e.into(),
+    };
+
+    match serde_json::from_slice::<Errors>(&body) {
+        Ok(envelope) if !envelope.errors.is_empty() => {
+            trace!("Registry errors: {:?}", envelope.errors);
+            Error::Registry {
+                status,
+                errors: envelope.errors,
+            }
+        }
+        _ if status.is_client_error() && !body.is_empty() => Error::Client {
+            status,
+            len: body.len(),
+            body: body.to_vec(),
+        },
+        _ => Error::UnexpectedHttpStatus(status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, body: &'static str) -> reqwest::Response {
+        http::Response::builder()
+            .status(status)
+            .body(body)
+            .expect("statically known response is valid")
+            .into()
+    }
+
+    #[tokio::test]
+    async fn registry_error_decodes_envelope() {
+        let body = r#"{"errors": [{"code": "TOOMANYREQUESTS", "message": "slow down"}, {"code": "SOMETHING_NEW", "message": "custom"}]}"#;
+
+        let err = registry_error(response(429, body)).await;
+
+        assert!(err.has_error_code(&ApiErrorCode::TooManyRequests));
+        assert_eq!(
+            err.registry_errors()[1].code,
+            ApiErrorCode::Other("SOMETHING_NEW".to_string())
+        );
+        assert_eq!(
+            err.to_string(),
+            "registry error 429 Too Many Requests: TOOMANYREQUESTS: slow down, SOMETHING_NEW: custom"
+        );
+    }
+
+    #[tokio::test]
+    async fn registry_error_keeps_unknown_client_body() {
+        let err = registry_error(response(404, "not found")).await;
+
+        match err {
+            Error::Client { status, body, .. } => {
+                assert_eq!(status, StatusCode::NOT_FOUND);
+                assert_eq!(body, b"not found");
+            }
+            e => panic!("expected a client error, got {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn registry_error_without_body() {
+        let err = registry_error(response(502, "")).await;
+
+        assert!(matches!(
+            err,
+            Error::UnexpectedHttpStatus(StatusCode::BAD_GATEWAY)
+        ));
+        assert!(err.registry_errors().is_empty());
+    }
+}
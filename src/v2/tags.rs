@@ -35,7 +35,7 @@ impl Client {
 
                 link = match last {
                     None => break,
-                    Some(ref s) if s == "" => None,
+                    Some(ref s) if s.is_empty() => None,
                     s => s,
                 };
             }
@@ -56,12 +56,14 @@ impl Client {
         };
         let url = Url::parse(&url_paginated)?;
 
-        let resp = self
+        let req = self
             .build_reqwest(Method::GET, url.clone())
-            .header(header::ACCEPT, "application/json")
-            .send()
-            .await?
-            .error_for_status()?;
+            .header(header::ACCEPT, "application/json");
+        let resp = self.send_request(req).await?;
+
+        if !resp.status().is_success() {
+            return Err(registry_error(resp).await);
+        }
 
         // ensure the CONTENT_TYPE header is application/json
         let ct_hdr = resp.headers().get(header::CONTENT_TYPE).cloned();
@@ -95,10 +97,7 @@ fn parse_link(hdr: Option<&header::HeaderValue>) -> Option<String> {
     // whether there is a a common library to do this, in the future.
 
     // Raw Header value bytes.
-    let hval = match hdr {
-        Some(v) => v,
-        None => return None,
-    };
+    let hval = hdr?;
 
     // Header value string.
     let sval = match hval.to_str() {
@@ -110,14 +109,14 @@ fn parse_link(hdr: Option<&header::HeaderValue>) -> Option<String> {
     let uri = sval.trim_end_matches(">; rel=\"next\"");
     let query: Vec<&str> = uri.splitn(2, "next_page=").collect();
     let params = match query.get(1) {
-        Some(v) if *v != "" => v,
+        Some(v) if !v.is_empty() => v,
         _ => return None,
     };
 
     // Last item in current page (pagination parameter).
     let last: Vec<&str> = params.splitn(2, '&').collect();
-    match last.get(0).cloned() {
-        Some(v) if v != "" => Some(v.to_string()),
+    match last.first().cloned() {
+        Some(v) if !v.is_empty() => Some(v.to_string()),
         _ => None,
     }
 }
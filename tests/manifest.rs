@@ -36,12 +36,12 @@ fn deserialize_manifest_v2s2_config(
         serde_json::from_reader::<_, dkregistry::v2::manifest::ConfigBlob>(f)?
     };
 
-    Ok(dkregistry::v2::manifest::Manifest::S2(
+    Ok(dkregistry::v2::manifest::Manifest::S2(Box::new(
         dkregistry::v2::manifest::ManifestSchema2 {
             manifest_spec,
             config_blob,
         },
-    ))
+    )))
 }
 
 #[test]
@@ -76,6 +76,126 @@ fn test_deserialize_manifest_list_v2() {
     let _manif: dkregistry::v2::manifest::ManifestList = serde_json::from_reader(bufrd).unwrap();
 }
 
+#[test]
+fn test_deserialize_manifest_oci() -> Result<(), Box<dyn std::error::Error>> {
+    let f = fs::File::open("tests/fixtures/manifest_oci.json").expect("Missing fixture");
+    let manif: dkregistry::v2::manifest::OciManifestSpec = serde_json::from_reader(f)?;
+
+    assert_eq!(
+        "application/vnd.oci.image.config.v1+json",
+        manif.config().media_type
+    );
+    assert_eq!(2, manif.layers.len());
+    assert_eq!(
+        Some("layer.tar.gz"),
+        manif.layers[1]
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get("org.opencontainers.image.title"))
+            .map(String::as_str)
+    );
+    assert_eq!(
+        "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270",
+        manif.subject.as_ref().ok_or("missing subject")?.digest
+    );
+    assert_eq!(2, manif.annotations.as_ref().map_or(0, |a| a.len()));
+
+    let manifest =
+        dkregistry::v2::manifest::Manifest::Oci(Box::new(dkregistry::v2::manifest::OciManifest {
+            manifest_spec: manif,
+            config_blob: None,
+        }));
+    assert_eq!(49378, manifest.download_size()?);
+    assert_eq!(2, manifest.layers_digests(None)?.len());
+
+    Ok(())
+}
+
+#[test]
+fn test_deserialize_manifest_oci_artifact() -> Result<(), Box<dyn std::error::Error>> {
+    let f = fs::File::open("tests/fixtures/manifest_oci_artifact.json").expect("Missing fixture");
+    let manif: dkregistry::v2::manifest::OciManifestSpec = serde_json::from_reader(f)?;
+
+    assert_eq!(
+        Some("application/vnd.example.sbom.v1"),
+        manif.artifact_type.as_deref()
+    );
+    assert_eq!(Some("e30="), manif.config().data.as_deref());
+    assert!(manif.subject.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_deserialize_index_oci() -> Result<(), Box<dyn std::error::Error>> {
+    let f = fs::File::open("tests/fixtures/index_oci.json").expect("Missing fixture");
+    let index: dkregistry::v2::manifest::OciIndex = serde_json::from_reader(f)?;
+
+    assert_eq!(3, index.manifests.len());
+    assert_eq!(
+        "amd64",
+        index.manifests[1]
+            .platform
+            .as_ref()
+            .ok_or("missing platform")?
+            .architecture
+    );
+    assert!(index.manifests[2].platform.is_none());
+    assert_eq!(
+        Some("application/vnd.example.sbom.v1"),
+        index.manifests[2].artifact_type.as_deref()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_manifest_list_v2_platforms() -> Result<(), Box<dyn std::error::Error>> {
+    let f = fs::File::open("tests/fixtures/manifest_list_v2.json").expect("Missing fixture");
+    let manifest = dkregistry::v2::manifest::Manifest::ML(serde_json::from_reader(f)?);
+
+    assert_eq!(vec!["ppc64le", "amd64"], manifest.architectures()?);
+    assert_eq!(2, manifest.platforms().len());
+    assert!(manifest.layers_digests(None).is_err());
+
+    let amd64 = dkregistry::v2::manifest::PlatformSpec::new("linux", "amd64", None);
+    assert_eq!(
+        Some("sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270".to_string()),
+        manifest.digest_for_platform(&amd64)?
+    );
+
+    let arm64 = dkregistry::v2::manifest::PlatformSpec::new("linux", "arm64", None);
+    assert!(manifest.digest_for_platform(&arm64).is_err());
+
+    let amd64_v2 = dkregistry::v2::manifest::PlatformSpec::new("linux", "amd64", Some("v2"));
+    assert_eq!(
+        Some("sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270".to_string()),
+        manifest.digest_for_platform(&amd64_v2)?
+    );
+
+    let arm = dkregistry::v2::manifest::PlatformSpec::new("linux", "arm", None);
+    assert!(manifest.digest_for_platform(&arm).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_index_oci_platforms() -> Result<(), Box<dyn std::error::Error>> {
+    let f = fs::File::open("tests/fixtures/index_oci.json").expect("Missing fixture");
+    let manifest =
+        dkregistry::v2::manifest::Manifest::OciIndex(Box::new(serde_json::from_reader(f)?));
+
+    assert_eq!(vec!["ppc64le", "amd64"], manifest.architectures()?);
+
+    let ppc64le = dkregistry::v2::manifest::PlatformSpec::new("linux", "ppc64le", None);
+    assert_eq!(
+        Some("sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f".to_string()),
+        manifest.digest_for_platform(&ppc64le)?
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_deserialize_etcd_manifest() {
     let f =
@@ -106,3 +226,135 @@ fn test_labels_manifest_v2s1_signed() {
     assert_eq!(expected_labels_0, labels_0);
     assert_eq!(None, manif.get_labels(1));
 }
+
+#[test]
+fn test_deserialize_config_blob_requires_architecture() {
+    let config_blob = serde_json::from_str::<dkregistry::v2::manifest::ConfigBlob>(
+        r#"{"os": "linux", "config": {"Healthcheck": {"Test": ["NONE"]}}}"#,
+    );
+    assert!(config_blob.is_err());
+
+    let config_blob = serde_json::from_str::<dkregistry::v2::manifest::ConfigBlob>(
+        r#"{"architecture": "amd64", "os": "linux", "config": {"Healthcheck": {"Test": ["NONE"]}}}"#,
+    )
+    .expect("valid config blob");
+    let healthcheck = config_blob.config.and_then(|c| c.healthcheck);
+    assert_eq!(Some(vec!["NONE".to_string()]), healthcheck.and_then(|h| h.test));
+}
+
+#[test]
+fn test_deserialize_container_config_blob() -> Result<(), Box<dyn std::error::Error>> {
+    let f = fs::File::open("tests/fixtures/container_config_blob.json").expect("Missing fixture");
+    let config_blob: dkregistry::v2::manifest::ConfigBlob = serde_json::from_reader(f)?;
+
+    assert_eq!("amd64", config_blob.architecture());
+    assert_eq!("linux", config_blob.os);
+    assert_eq!(Some("2019-08-16T14:50:54Z"), config_blob.created.as_deref());
+
+    let config = config_blob.config.as_ref().ok_or("missing config")?;
+    assert_eq!(
+        Some(vec!["/usr/bin/cluster-version-operator".to_string()]),
+        config.entrypoint
+    );
+    assert_eq!(Some("0"), config.user.as_deref());
+    assert_eq!(8, config.env.as_ref().map_or(0, Vec::len));
+    assert_eq!(Some("9998c79647f2"), config.hostname.as_deref());
+
+    assert_eq!(Some("1.13.1"), config_blob.docker_version.as_deref());
+    assert_eq!(Some(100242846), config_blob.size);
+    assert!(config_blob.container_config.is_some());
+
+    let rootfs = config_blob.rootfs.as_ref().ok_or("missing rootfs")?;
+    assert_eq!("layers", rootfs.fs_type);
+    assert_eq!(6, rootfs.diff_ids.len());
+    assert_eq!(6, config_blob.history.len());
+    assert_eq!(
+        Some("Release image for OpenShift"),
+        config_blob.history[0].comment.as_deref()
+    );
+
+    let manifest = dkregistry::v2::manifest::Manifest::S2(Box::new(
+        dkregistry::v2::manifest::ManifestSchema2 {
+            manifest_spec: Default::default(),
+            config_blob,
+        },
+    ));
+    let labels = manifest.labels().ok_or("missing labels")?;
+    assert_eq!(Some("4.1.12"), labels.get("io.openshift.release").map(String::as_str));
+
+    Ok(())
+}
+
+#[test]
+fn test_labels_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let f =
+        fs::File::open("tests/fixtures/quayio_steveej_cincinnati-test-labels_dkregistry-test.json")
+            .expect("Missing fixture");
+    let manifest =
+        dkregistry::v2::manifest::Manifest::S1Signed(Box::new(serde_json::from_reader(f)?));
+    let mut expected_labels: HashMap<String, String> = HashMap::new();
+    expected_labels.insert("channel".into(), "beta".into());
+    assert_eq!(Some(expected_labels), manifest.labels());
+
+    let f = fs::File::open("tests/fixtures/manifest_list_v2.json").expect("Missing fixture");
+    let manifest = dkregistry::v2::manifest::Manifest::ML(serde_json::from_reader(f)?);
+    assert_eq!(None, manifest.labels());
+
+    Ok(())
+}
+
+#[test]
+fn test_layers_manifest_v2s2_foreign() -> Result<(), Box<dyn std::error::Error>> {
+    use dkregistry::v2::manifest::LayerCompression;
+
+    let f = fs::File::open("tests/fixtures/manifest_v2_s2_foreign.json").expect("Missing fixture");
+    let manifest = dkregistry::v2::manifest::Manifest::S2(Box::new(
+        dkregistry::v2::manifest::ManifestSchema2 {
+            manifest_spec: serde_json::from_reader(f)?,
+            config_blob: Default::default(),
+        },
+    ));
+
+    let layers = manifest.layers()?;
+    assert_eq!(2, layers.len());
+
+    assert!(!layers[0].is_distributable());
+    assert_eq!(Some(LayerCompression::Gzip), layers[0].compression());
+    assert_eq!(
+        Some(vec!["https://go.microsoft.com/fwlink/?linkid=837858".to_string()]),
+        layers[0].urls
+    );
+
+    assert!(layers[1].is_distributable());
+    assert_eq!(1231, layers[1].size);
+    assert_eq!(
+        manifest.download_size()?,
+        layers.iter().map(|l| l.size).sum::<u64>()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_layers_manifest_oci() -> Result<(), Box<dyn std::error::Error>> {
+    let f = fs::File::open("tests/fixtures/manifest_oci.json").expect("Missing fixture");
+    let manifest =
+        dkregistry::v2::manifest::Manifest::Oci(Box::new(dkregistry::v2::manifest::OciManifest {
+            manifest_spec: serde_json::from_reader(f)?,
+            config_blob: None,
+        }));
+
+    let layers = manifest.layers()?;
+    assert_eq!(
+        manifest.layers_digests(None)?,
+        layers.iter().map(|l| l.digest.clone()).collect::<Vec<_>>()
+    );
+    assert!(layers.iter().all(|l| l.is_distributable()));
+
+    let f = fs::File::open("tests/fixtures/manifest_v2_s1.json").expect("Missing fixture");
+    let manifest =
+        dkregistry::v2::manifest::Manifest::S1Signed(Box::new(serde_json::from_reader(f)?));
+    assert!(manifest.layers().is_err());
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate sha2;
+extern crate tokio;
+
+use self::mockito::mock;
+use self::tokio::runtime::Runtime;
+use crate::mock::blobs_delete::sha2::Digest;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[test]
+fn test_blobs_delete() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let m = mock("DELETE", ep.as_str()).with_status(202).create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.delete_blob(name, &digest);
+
+    runtime.block_on(futcheck)?;
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_delete_unknown() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+    let body = r#"{"errors": [{"code": "BLOB_UNKNOWN", "message": "blob unknown to registry", "detail": {"digest": "sha256:abc"}}]}"#;
+
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let _m = mock("DELETE", ep.as_str())
+        .with_status(404)
+        .with_header("Content-Type", "application/json")
+        .with_body(body)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.delete_blob(name, &digest);
+
+    match runtime.block_on(futcheck) {
+        Err(dkregistry::errors::Error::Registry { status, errors }) => {
+            assert_eq!(status, 404);
+            assert_eq!(errors[0].code, dkregistry::v2::ApiErrorCode::BlobUnknown);
+            assert_eq!(errors[0].message, "blob unknown to registry");
+        }
+        res => return Err(format!("expected a registry error, got {:?}", res).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
@@ -1,8 +1,10 @@
 extern crate dkregistry;
+extern crate futures;
 extern crate mockito;
 extern crate sha2;
 extern crate tokio;
 
+use self::futures::TryStreamExt;
 use self::mockito::mock;
 use self::tokio::runtime::Runtime;
 use crate::mock::blobs_download::sha2::Digest;
@@ -131,3 +133,637 @@ fn get_blobs_fails_with_inconsistent_layer() -> Fallible<()> {
     mockito::reset();
     Ok(())
 }
+
+#[test]
+fn get_blobs_fails_with_registry_error() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+    let body = r#"{"errors": [{"code": "BLOB_UNKNOWN", "message": "blob unknown to registry"}]}"#;
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(404)
+        .with_header("Content-Type", "application/json")
+        .with_body(body)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_blob(name, &digest);
+
+    let err = match runtime.block_on(futcheck) {
+        Ok(_) => return Err("expected get_blob to fail for an unknown blob".into()),
+        Err(e) => e,
+    };
+    assert!(err.has_error_code(&dkregistry::v2::ApiErrorCode::BlobUnknown));
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_stream_succeeds_with_consistent_layer() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let result = runtime.block_on(async {
+        let stream = dclient.get_blob_stream(name, &digest).await?;
+        stream
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await
+    })?;
+    assert_eq!(blob, result.as_slice());
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_stream_fails_with_inconsistent_layer() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let blob2 = b"hello2";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob2)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let result = runtime.block_on(async {
+        let stream = dclient.get_blob_stream(name, &digest).await?;
+        stream.try_collect::<Vec<_>>().await
+    });
+    match result {
+        Err(dkregistry::errors::Error::ContentDigestParse(_)) => {}
+        res => return Err(format!("expected a digest verification error, got {:?}", res).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_stream_fails_with_registry_error() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(404)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"errors": [{"code": "BLOB_UNKNOWN", "message": "blob unknown to registry"}]}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_blob_stream(name, &digest)) {
+        Err(e) => assert!(e.has_error_code(&dkregistry::v2::ApiErrorCode::BlobUnknown)),
+        Ok(_) => return Err("expected get_blob_stream to fail for an unknown blob".into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_with_progress_file_writes_verified_blob() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let target_dir = std::env::temp_dir().join("dkregistry-test-progress-file-ok");
+    let _ = std::fs::remove_dir_all(&target_dir);
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let futcheck = dclient.get_blob_with_progress_file(name, &digest, Some(sender), &target_dir);
+
+    let path = runtime.block_on(futcheck)?;
+    assert_eq!(target_dir.join(&digest), path);
+    assert_eq!(blob, std::fs::read(&path)?.as_slice());
+    assert!(!target_dir.join(format!("{}.partial", digest)).exists());
+    assert_eq!(blob.len() as u64, receiver.try_iter().sum::<u64>());
+
+    std::fs::remove_dir_all(&target_dir)?;
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_with_progress_file_fails_with_inconsistent_layer() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+    let target_dir = std::env::temp_dir().join("dkregistry-test-progress-file-bad");
+    let _ = std::fs::remove_dir_all(&target_dir);
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(b"hello2")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_blob_with_progress_file(name, &digest, None, &target_dir);
+
+    if runtime.block_on(futcheck).is_ok() {
+        return Err("expected get_blob_with_progress_file to fail with an inconsistent blob".into());
+    }
+    assert!(!target_dir.join(&digest).exists());
+    assert!(!target_dir.join(format!("{}.partial", digest)).exists());
+
+    std::fs::remove_dir_all(&target_dir)?;
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_with_progress_file_resumes_partial_download() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let target_dir = std::env::temp_dir().join("dkregistry-test-progress-file-resume");
+    let _ = std::fs::remove_dir_all(&target_dir);
+    std::fs::create_dir_all(&target_dir)?;
+    std::fs::write(target_dir.join(format!("{}.partial", digest)), b"hel")?;
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let m = mock("GET", ep.as_str())
+        .match_header("Range", "bytes=3-")
+        .with_status(206)
+        .with_header("Content-Range", "bytes 3-4/5")
+        .with_body(b"lo")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let futcheck = dclient.get_blob_with_progress_file(name, &digest, Some(sender), &target_dir);
+
+    let path = runtime.block_on(futcheck)?;
+    m.assert();
+    assert_eq!(blob, std::fs::read(&path)?.as_slice());
+    assert_eq!(blob.len() as u64, receiver.try_iter().sum::<u64>());
+
+    std::fs::remove_dir_all(&target_dir)?;
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_with_progress_file_restarts_without_range_support() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let target_dir = std::env::temp_dir().join("dkregistry-test-progress-file-restart");
+    let _ = std::fs::remove_dir_all(&target_dir);
+    std::fs::create_dir_all(&target_dir)?;
+    std::fs::write(target_dir.join(format!("{}.partial", digest)), b"hel")?;
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_blob_with_progress_file(name, &digest, None, &target_dir);
+
+    let path = runtime.block_on(futcheck)?;
+    assert_eq!(blob, std::fs::read(&path)?.as_slice());
+
+    std::fs::remove_dir_all(&target_dir)?;
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_with_progress_file_completes_on_range_not_satisfiable() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let target_dir = std::env::temp_dir().join("dkregistry-test-progress-file-complete");
+    let _ = std::fs::remove_dir_all(&target_dir);
+    std::fs::create_dir_all(&target_dir)?;
+    std::fs::write(target_dir.join(format!("{}.partial", digest)), blob)?;
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let m = mock("GET", ep.as_str())
+        .match_header("Range", "bytes=5-")
+        .with_status(416)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_blob_with_progress_file(name, &digest, None, &target_dir);
+
+    let path = runtime.block_on(futcheck)?;
+    m.assert();
+    assert_eq!(blob, std::fs::read(&path)?.as_slice());
+
+    std::fs::remove_dir_all(&target_dir)?;
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_with_progress_file_keeps_partial_on_error() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+    let target_dir = std::env::temp_dir().join("dkregistry-test-progress-file-keep");
+    let partial = target_dir.join(format!("{}.partial", digest));
+    let _ = std::fs::remove_dir_all(&target_dir);
+    std::fs::create_dir_all(&target_dir)?;
+    std::fs::write(&partial, b"hel")?;
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str()).with_status(503).create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_blob_with_progress_file(name, &digest, None, &target_dir);
+
+    if runtime.block_on(futcheck).is_ok() {
+        return Err("expected get_blob_with_progress_file to fail".into());
+    }
+    assert_eq!(b"hel", std::fs::read(&partial)?.as_slice());
+
+    std::fs::remove_dir_all(&target_dir)?;
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_with_progress_file_leaves_no_partial_on_error() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+    let target_dir = std::env::temp_dir().join("dkregistry-test-progress-file-error");
+    let partial = target_dir.join(format!("{}.partial", digest));
+    let _ = std::fs::remove_dir_all(&target_dir);
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str()).with_status(404).create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_blob_with_progress_file(name, &digest, None, &target_dir);
+
+    if runtime.block_on(futcheck).is_ok() {
+        return Err("expected get_blob_with_progress_file to fail".into());
+    }
+    assert!(!partial.exists());
+
+    std::fs::remove_dir_all(&target_dir)?;
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_stream_ranged_reassembles_ranges() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello world";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _h = mock("HEAD", ep.as_str())
+        .with_status(200)
+        .with_header("Accept-Ranges", "bytes")
+        .with_header("Content-Length", &blob.len().to_string())
+        .create();
+    let ranges = [(0, 3), (4, 7), (8, 10)];
+    let mocks = ranges
+        .iter()
+        .map(|&(start, end)| {
+            mock("GET", ep.as_str())
+                .match_header("Range", format!("bytes={}-{}", start, end).as_str())
+                .with_status(206)
+                .with_header(
+                    "Content-Range",
+                    &format!("bytes {}-{}/{}", start, end, blob.len()),
+                )
+                .with_body(&blob[start..=end])
+                .create()
+        })
+        .collect::<Vec<_>>();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let result = runtime.block_on(async {
+        let stream = dclient.get_blob_stream_ranged(name, &digest, 4, 2).await?;
+        stream.map_ok(|b| b.to_vec()).try_concat().await
+    })?;
+    assert_eq!(blob, result.as_slice());
+    for m in mocks {
+        m.assert();
+    }
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_stream_ranged_falls_back_without_range_support() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello world";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _h = mock("HEAD", ep.as_str())
+        .with_status(200)
+        .with_header("Content-Length", &blob.len().to_string())
+        .create();
+    let m = mock("GET", ep.as_str())
+        .match_header("Range", mockito::Matcher::Missing)
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let result = runtime.block_on(async {
+        let stream = dclient.get_blob_stream_ranged(name, &digest, 4, 2).await?;
+        stream.map_ok(|b| b.to_vec()).try_concat().await
+    })?;
+    assert_eq!(blob, result.as_slice());
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_stream_ranged_fails_with_inconsistent_layer() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"world"));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _h = mock("HEAD", ep.as_str())
+        .with_status(200)
+        .with_header("Accept-Ranges", "bytes")
+        .with_header("Content-Length", &blob.len().to_string())
+        .create();
+    let _m = mock("GET", ep.as_str())
+        .match_header("Range", "bytes=0-4")
+        .with_status(206)
+        .with_header("Content-Range", "bytes 0-4/5")
+        .with_body(blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let result = runtime.block_on(async {
+        let stream = dclient.get_blob_stream_ranged(name, &digest, 8, 2).await?;
+        stream.map_ok(|b| b.to_vec()).try_concat().await
+    });
+    match result {
+        Err(dkregistry::errors::Error::ContentDigestParse(_)) => {}
+        res => return Err(format!("expected a digest verification error, got {:?}", res).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_layer_fetches_foreign_layer_from_urls() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+    let foreign = format!("http://localhost:{}", mockito::server_address().port());
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let r = mock("GET", ep.as_str()).with_status(404).expect(0).create();
+    let f = mock("GET", "/foreign/layer.tar.gz")
+        .match_header("Authorization", mockito::Matcher::Missing)
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let layer = dkregistry::v2::manifest::Descriptor {
+        media_type: "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip".to_string(),
+        digest: digest.clone(),
+        size: blob.len() as u64,
+        urls: Some(vec![format!("{}/foreign/layer.tar.gz", foreign)]),
+        ..Default::default()
+    };
+
+    let result = runtime.block_on(dclient.get_layer(name, &layer))?;
+    assert_eq!(blob, result.as_slice());
+    f.assert();
+    r.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_layer_falls_back_to_registry() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+    let foreign = format!("http://localhost:{}", mockito::server_address().port());
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let r = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+    let _missing = mock("GET", "/foreign/missing.tar.gz")
+        .with_status(404)
+        .create();
+    let _corrupt = mock("GET", "/foreign/corrupt.tar.gz")
+        .with_status(200)
+        .with_body(b"hello2")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let layer = dkregistry::v2::manifest::Descriptor {
+        media_type: "application/vnd.oci.image.layer.nondistributable.v1.tar+gzip".to_string(),
+        digest: digest.clone(),
+        size: blob.len() as u64,
+        urls: Some(vec![
+            format!("{}/foreign/missing.tar.gz", foreign),
+            format!("{}/foreign/corrupt.tar.gz", foreign),
+            "ftp://example.com/layer.tar.gz".to_string(),
+        ]),
+        ..Default::default()
+    };
+
+    let result = runtime.block_on(dclient.get_layer(name, &layer))?;
+    assert_eq!(blob, result.as_slice());
+    r.assert();
+
+    mockito::reset();
+    Ok(())
+}
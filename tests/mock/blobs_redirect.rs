@@ -0,0 +1,180 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate sha2;
+extern crate tokio;
+
+use self::mockito::{mock, Matcher};
+use self::tokio::runtime::Runtime;
+use crate::mock::blobs_redirect::sha2::Digest;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Build a client which authenticates with basic auth against the mock registry.
+fn authenticated_client(runtime: &Runtime, addr: &str) -> Fallible<dkregistry::v2::Client> {
+    let _m = mock("GET", "/v2/")
+        .with_status(401)
+        .with_header("WWW-Authenticate", r#"Basic realm="mock""#)
+        .create();
+
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(addr)
+        .insecure_registry(true)
+        .username(Some("user".to_string()))
+        .password(Some("password".to_string()))
+        .build()?;
+
+    Ok(runtime.block_on(dclient.authenticate(&[]))?)
+}
+
+#[test]
+fn get_blob_drops_auth_on_cross_host_redirect() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+    let storage = format!("http://localhost:{}", mockito::server_address().port());
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = authenticated_client(&runtime, &addr)?;
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let r = mock("GET", ep.as_str())
+        .match_header("Authorization", Matcher::Regex("^Basic ".to_string()))
+        .with_status(307)
+        .with_header("Location", &format!("{}/storage/blob?signature=abc", storage))
+        .create();
+    let s = mock("GET", "/storage/blob")
+        .match_query(Matcher::UrlEncoded("signature".into(), "abc".into()))
+        .match_header("Authorization", Matcher::Missing)
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let result = runtime.block_on(dclient.get_blob(name, &digest))?;
+    assert_eq!(blob, result.as_slice());
+    r.assert();
+    s.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_keeps_auth_on_same_host_redirect() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = authenticated_client(&runtime, &addr)?;
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _r = mock("GET", ep.as_str())
+        .with_status(307)
+        .with_header("Location", "/storage/blob")
+        .create();
+    let s = mock("GET", "/storage/blob")
+        .match_header("Authorization", Matcher::Regex("^Basic ".to_string()))
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let result = runtime.block_on(dclient.get_blob(name, &digest))?;
+    assert_eq!(blob, result.as_slice());
+    s.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_fails_on_redirect_loop() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _r = mock("GET", ep.as_str())
+        .with_status(302)
+        .with_header("Location", &ep)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()?;
+
+    match runtime.block_on(dclient.get_blob(name, &digest)) {
+        Err(dkregistry::errors::Error::TooManyRedirects(_)) => {}
+        res => return Err(format!("expected too many redirects, got {:?}", res).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_location_returns_redirect_target() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+    let target = "https://storage.example.com/blob?signature=abc";
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let m = mock("GET", ep.as_str())
+        .with_status(307)
+        .with_header("Location", target)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()?;
+
+    let location = runtime.block_on(dclient.get_blob_location(name, &digest))?;
+    assert_eq!(target, location.as_str());
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_location_without_redirect() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body("hello")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()?;
+
+    let location = runtime.block_on(dclient.get_blob_location(name, &digest))?;
+    assert_eq!(format!("http://{}{}", addr, ep), location.as_str());
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
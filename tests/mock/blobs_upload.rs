@@ -0,0 +1,274 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate sha2;
+extern crate tokio;
+
+use self::mockito::mock;
+use self::tokio::runtime::Runtime;
+use crate::mock::blobs_upload::sha2::Digest;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[test]
+fn test_blobs_upload_monolithic() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let uuid = "3e8e4d64-6de2-4e6b-9e9b-1b2e6a6f7e0a";
+
+    let ep_start = format!("/v2/{}/blobs/uploads/", name);
+    let ep_session = format!("/v2/{}/blobs/uploads/{}", name, uuid);
+    let _m_start = mock("POST", ep_start.as_str())
+        .with_status(202)
+        .with_header("Location", &ep_session)
+        .with_header("Docker-Upload-UUID", uuid)
+        .with_header("Range", "0-0")
+        .create();
+    let m_put = mock("PUT", ep_session.as_str())
+        .match_query(mockito::Matcher::UrlEncoded(
+            "digest".to_string(),
+            digest.clone(),
+        ))
+        .match_header("Content-Type", "application/octet-stream")
+        .match_body("hello")
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.upload_blob(name, &digest, blob);
+
+    let res = runtime.block_on(futcheck)?;
+    assert_eq!(res, digest);
+    m_put.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_upload_chunked() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello world";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep_start = format!("/v2/{}/blobs/uploads/", name);
+    let ep_session = format!("/v2/{}/blobs/uploads/session", name);
+    let _m_start = mock("POST", ep_start.as_str())
+        .with_status(202)
+        .with_header("Location", &format!("{}?_state=0", ep_session))
+        .create();
+    let m_chunk1 = mock("PATCH", ep_session.as_str())
+        .match_query("_state=0")
+        .match_header("Content-Range", "0-5")
+        .match_body("hello ")
+        .with_status(202)
+        .with_header("Location", &format!("{}?_state=1", ep_session))
+        .with_header("Range", "0-5")
+        .create();
+    let m_chunk2 = mock("PATCH", ep_session.as_str())
+        .match_query("_state=1")
+        .match_header("Content-Range", "6-10")
+        .match_body("world")
+        .with_status(202)
+        .with_header("Location", &format!("{}?_state=2", ep_session))
+        .with_header("Range", "0-10")
+        .create();
+    let m_put = mock("PUT", ep_session.as_str())
+        .match_query(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("_state".to_string(), "2".to_string()),
+            mockito::Matcher::UrlEncoded("digest".to_string(), digest.clone()),
+        ]))
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.upload_blob_chunked(name, &digest, blob, 6);
+
+    let res = runtime.block_on(futcheck)?;
+    assert_eq!(res, digest);
+    m_chunk1.assert();
+    m_chunk2.assert();
+    m_put.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_upload_fails_with_inconsistent_blob() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let blob2 = b"hello2";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep_start = format!("/v2/{}/blobs/uploads/", name);
+    let m_start = mock("POST", ep_start.as_str())
+        .with_status(202)
+        .expect(0)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.upload_blob(name, &digest, blob2);
+
+    if runtime.block_on(futcheck).is_ok() {
+        return Err("expected upload_blob to fail with an inconsistent blob".into());
+    };
+    m_start.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_upload_fails_with_digest_mismatch() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let other_digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello2"));
+
+    let ep_start = format!("/v2/{}/blobs/uploads/", name);
+    let ep_session = format!("/v2/{}/blobs/uploads/session", name);
+    let _m_start = mock("POST", ep_start.as_str())
+        .with_status(202)
+        .with_header("Location", &ep_session)
+        .create();
+    let _m_put = mock("PUT", ep_session.as_str())
+        .match_query(mockito::Matcher::Any)
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &other_digest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.upload_blob(name, &digest, blob);
+
+    if runtime.block_on(futcheck).is_ok() {
+        return Err("expected upload_blob to fail with a mismatching digest".into());
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_mount_created() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let from = "my-repo/other-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let m = mock("POST", ep.as_str())
+        .match_query(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("mount".to_string(), digest.clone()),
+            mockito::Matcher::UrlEncoded("from".to_string(), from.to_string()),
+        ]))
+        .with_status(201)
+        .with_header("Location", &format!("/v2/{}/blobs/{}", name, digest))
+        .with_header("Docker-Content-Digest", &digest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.mount_blob(name, &digest, from);
+
+    match runtime.block_on(futcheck)? {
+        dkregistry::v2::BlobMount::Mounted(mounted) => assert_eq!(mounted, digest),
+        other => return Err(format!("expected mounted blob, got {:?}", other).into()),
+    };
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_mount_fallback_to_upload() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let from = "my-repo/other-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+    let uuid = "3e8e4d64-6de2-4e6b-9e9b-1b2e6a6f7e0a";
+
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let ep_session = format!("/v2/{}/blobs/uploads/{}", name, uuid);
+    let _m = mock("POST", ep.as_str())
+        .match_query(mockito::Matcher::Any)
+        .with_status(202)
+        .with_header("Location", &ep_session)
+        .with_header("Docker-Upload-UUID", uuid)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.mount_blob(name, &digest, from);
+
+    match runtime.block_on(futcheck)? {
+        dkregistry::v2::BlobMount::Upload(upload) => {
+            assert_eq!(upload.uuid(), Some(uuid));
+            assert_eq!(upload.location().path(), ep_session);
+            assert_eq!(upload.offset(), 0);
+        }
+        other => return Err(format!("expected upload session, got {:?}", other).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
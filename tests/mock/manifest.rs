@@ -0,0 +1,570 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate serde_json;
+extern crate sha2;
+extern crate tokio;
+
+use self::mockito::mock;
+use self::tokio::runtime::Runtime;
+use crate::mock::manifest::sha2::Digest;
+use std::fs;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Digest of the amd64 manifest in `manifest_list_v2.json`.
+const AMD64_MANIFEST_DIGEST: &str =
+    "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270";
+
+/// Digest of the configuration in the manifest fixtures.
+const FIXTURE_CONFIG_DIGEST: &str =
+    "sha256:b5b2b2c507a0944348e0303114d8d93aaaa081732b86451d9bce1f432a537bc7";
+
+fn sha256(content: &[u8]) -> String {
+    format!("sha256:{:x}", sha2::Sha256::digest(content))
+}
+
+/// Point a manifest fixture at the given configuration content.
+fn with_config(manifest: String, config: &str) -> String {
+    manifest.replace(FIXTURE_CONFIG_DIGEST, &sha256(config.as_bytes()))
+}
+
+#[test]
+fn test_manifest_put_v2s2() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let manifest_spec: dkregistry::v2::manifest::ManifestSchema2Spec =
+        serde_json::from_reader(fs::File::open("tests/fixtures/manifest_v2_s2.json")?)?;
+    let body = serde_json::to_vec(&manifest_spec)?;
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(&body));
+    let manifest = dkregistry::v2::manifest::Manifest::S2(Box::new(
+        dkregistry::v2::manifest::ManifestSchema2 {
+            manifest_spec,
+            config_blob: Default::default(),
+        },
+    ));
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let m = mock("PUT", ep.as_str())
+        .match_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .match_body(mockito::Matcher::Json(serde_json::from_slice(&body)?))
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.put_manifest(name, reference, &manifest);
+
+    let res = runtime.block_on(futcheck)?;
+    assert_eq!(res, digest);
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_put_list() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let manifest_list: dkregistry::v2::manifest::ManifestList =
+        serde_json::from_reader(fs::File::open("tests/fixtures/manifest_list_v2.json")?)?;
+    let body = serde_json::to_vec(&manifest_list)?;
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(&body));
+    let manifest = dkregistry::v2::manifest::Manifest::ML(manifest_list);
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let m = mock("PUT", ep.as_str())
+        .match_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.put_manifest(name, reference, &manifest);
+
+    let res = runtime.block_on(futcheck)?;
+    assert_eq!(res, digest);
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_put_v2s1_signed_unsupported() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let manifest = dkregistry::v2::manifest::Manifest::S1Signed(Box::new(serde_json::from_reader(
+        fs::File::open("tests/fixtures/manifest_v2_s1.json")?,
+    )?));
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let m = mock("PUT", ep.as_str()).with_status(201).expect(0).create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.put_manifest(name, reference, &manifest);
+
+    match runtime.block_on(futcheck) {
+        Ok(_) => return Err("expected put_manifest to reject a signed schema1 manifest".into()),
+        Err(dkregistry::errors::Error::Manifest(
+            dkregistry::v2::manifest::ManifestError::UploadUnsupported(media_type),
+        )) => assert_eq!(
+            media_type,
+            "application/vnd.docker.distribution.manifest.v1+prettyjws"
+        ),
+        Err(e) => return Err(e.into()),
+    };
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_put_raw_keeps_content() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    // Unknown fields and formatting must survive the upload.
+    let body = fs::read("tests/fixtures/manifest_v2_s2.json")?;
+    let mut value: serde_json::Value = serde_json::from_slice(&body)?;
+    value["x-custom"] = serde_json::json!({"kept": true});
+    let body = serde_json::to_vec_pretty(&value)?;
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(&body));
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let m = mock("PUT", ep.as_str())
+        .match_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .match_body(body.clone())
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.put_manifest_raw(
+        name,
+        reference,
+        dkregistry::mediatypes::MediaTypes::ManifestV2S2,
+        &body,
+    );
+
+    let res = runtime.block_on(futcheck)?;
+    assert_eq!(res, digest);
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_delete() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"manifest"));
+
+    let ep = format!("/v2/{}/manifests/{}", name, digest);
+    let addr = mockito::server_address().to_string();
+    let m = mock("DELETE", ep.as_str()).with_status(202).create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.delete_manifest(name, &digest);
+
+    runtime.block_on(futcheck)?;
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_delete_unsupported() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"manifest"));
+    let body =
+        r#"{"errors": [{"code": "UNSUPPORTED", "message": "The operation is unsupported."}]}"#;
+
+    let ep = format!("/v2/{}/manifests/{}", name, digest);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("DELETE", ep.as_str())
+        .with_status(405)
+        .with_header("Content-Type", "application/json")
+        .with_body(body)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.delete_manifest(name, &digest);
+
+    match runtime.block_on(futcheck) {
+        Err(dkregistry::errors::Error::Registry { status, errors }) => {
+            assert_eq!(status, 405);
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code, dkregistry::v2::ApiErrorCode::Unsupported);
+        }
+        res => return Err(format!("expected a registry error, got {:?}", res).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_delete_rejects_tag() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let m = mock("DELETE", ep.as_str())
+        .with_status(202)
+        .expect(0)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.delete_manifest(name, reference);
+
+    if runtime.block_on(futcheck).is_ok() {
+        return Err("expected delete_manifest to reject a tag".into());
+    };
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_get_oci() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let config_body = r#"{"architecture": "arm64", "os": "linux"}"#;
+    let manifest_body = with_config(
+        fs::read_to_string("tests/fixtures/manifest_oci.json")?,
+        config_body,
+    );
+    let manifest_spec: dkregistry::v2::manifest::OciManifestSpec =
+        serde_json::from_str(&manifest_body)?;
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let config_ep = format!("/v2/{}/blobs/{}", name, manifest_spec.config().digest);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .match_header(
+            "Accept",
+            mockito::Matcher::Regex("application/vnd.oci.image.manifest.v1\\+json".to_string()),
+        )
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .with_body(&manifest_body)
+        .create();
+    let _c = mock("GET", config_ep.as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.config.v1+json")
+        .with_body(config_body)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_manifest(name, reference);
+
+    let manifest = runtime.block_on(futcheck)?;
+    assert_eq!(vec!["arm64".to_string()], manifest.architectures()?);
+    match manifest {
+        dkregistry::v2::manifest::Manifest::Oci(m) => {
+            assert_eq!(2, m.manifest_spec.layers.len());
+            assert!(m.manifest_spec.subject.is_some());
+        }
+        m => return Err(format!("expected an OCI manifest, got {:?}", m).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_get_oci_index() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let index_body = fs::read_to_string("tests/fixtures/index_oci.json")?;
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .match_header(
+            "Accept",
+            mockito::Matcher::Regex("application/vnd.oci.image.index.v1\\+json".to_string()),
+        )
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .with_body(&index_body)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_manifest(name, reference);
+
+    match runtime.block_on(futcheck)? {
+        dkregistry::v2::manifest::Manifest::OciIndex(index) => {
+            assert_eq!(3, index.manifests.len());
+        }
+        m => return Err(format!("expected an OCI index, got {:?}", m).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_get_for_platform() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let config_body = r#"{"architecture": "amd64", "os": "linux"}"#;
+    let manifest_body = with_config(
+        fs::read_to_string("tests/fixtures/manifest_v2_s2.json")?,
+        config_body,
+    );
+    let manifest_spec: dkregistry::v2::manifest::ManifestSchema2Spec =
+        serde_json::from_str(&manifest_body)?;
+    let child = sha256(manifest_body.as_bytes());
+    let list_body = fs::read_to_string("tests/fixtures/manifest_list_v2.json")?
+        .replace(AMD64_MANIFEST_DIGEST, &child);
+
+    let addr = mockito::server_address().to_string();
+    let list_ep = format!("/v2/{}/manifests/{}", name, reference);
+    let _l = mock("GET", list_ep.as_str())
+        .match_header(
+            "Accept",
+            mockito::Matcher::Regex(
+                "application/vnd.docker.distribution.manifest.list.v2\\+json".to_string(),
+            ),
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(&list_body)
+        .create();
+    let child_ep = format!("/v2/{}/manifests/{}", name, child);
+    let m = mock("GET", child_ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest_body)
+        .create();
+    let config_ep = format!("/v2/{}/blobs/{}", name, manifest_spec.config().digest);
+    let _c = mock("GET", config_ep.as_str())
+        .with_status(200)
+        .with_body(config_body)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let platform = dkregistry::v2::manifest::PlatformSpec::new("linux", "amd64", None);
+    let futcheck = dclient.get_manifest_for_platform(name, reference, &platform);
+
+    let manifest = runtime.block_on(futcheck)?;
+    assert_eq!(vec!["amd64".to_string()], manifest.architectures()?);
+    assert_eq!(3, manifest.layers_digests(Some("amd64"))?.len());
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_get_for_platform_verifies_digest() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    // The list points at a manifest with different content.
+    let list_body = fs::read_to_string("tests/fixtures/manifest_list_v2.json")?;
+    let manifest_body = fs::read_to_string("tests/fixtures/manifest_v2_s2.json")?;
+
+    let addr = mockito::server_address().to_string();
+    let list_ep = format!("/v2/{}/manifests/{}", name, reference);
+    let _l = mock("GET", list_ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(&list_body)
+        .create();
+    let child_ep = format!("/v2/{}/manifests/{}", name, AMD64_MANIFEST_DIGEST);
+    let m = mock("GET", child_ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest_body)
+        .expect(1)
+        .create();
+    let c = mock("GET", mockito::Matcher::Regex("/blobs/".to_string()))
+        .expect(0)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let platform = dkregistry::v2::manifest::PlatformSpec::new("linux", "amd64", None);
+    let futcheck = dclient.get_manifest_for_platform(name, reference, &platform);
+
+    match runtime.block_on(futcheck) {
+        Err(dkregistry::errors::Error::ContentDigestParse(_)) => {}
+        res => return Err(format!("expected a digest mismatch, got {:?}", res).into()),
+    }
+    m.assert();
+    c.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_get_verifies_config_digest() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let manifest_body = with_config(
+        fs::read_to_string("tests/fixtures/manifest_v2_s2.json")?,
+        r#"{"architecture": "amd64", "os": "linux"}"#,
+    );
+    let manifest_spec: dkregistry::v2::manifest::ManifestSchema2Spec =
+        serde_json::from_str(&manifest_body)?;
+
+    let addr = mockito::server_address().to_string();
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest_body)
+        .create();
+    let config_ep = format!("/v2/{}/blobs/{}", name, manifest_spec.config().digest);
+    let _c = mock("GET", config_ep.as_str())
+        .with_status(200)
+        .with_body(r#"{"architecture": "arm64", "os": "linux"}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_manifest(name, reference)) {
+        Err(dkregistry::errors::Error::ContentDigestParse(_)) => {}
+        res => return Err(format!("expected a digest mismatch, got {:?}", res).into()),
+    }
+
+    mockito::reset();
+    Ok(())
+}
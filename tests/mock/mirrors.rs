@@ -0,0 +1,224 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate sha2;
+extern crate tokio;
+
+use self::dkregistry::v2::Mirror;
+use self::mockito::{mock, Matcher};
+use self::tokio::runtime::Runtime;
+use crate::mock::mirrors::sha2::Digest;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Mirror on the mock server, told apart from the registry by its namespace.
+fn mock_mirror(namespace: &str) -> Mirror {
+    let location = format!(
+        "localhost:{}/{}",
+        mockito::server_address().port(),
+        namespace
+    );
+    Mirror::new(&location).insecure(true)
+}
+
+/// Mirror which cannot be reached.
+fn unreachable_mirror() -> Mirror {
+    Mirror::new("127.0.0.1:1").insecure(true)
+}
+
+#[test]
+fn get_blob_from_mirror() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+    let name = "library/busybox";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mirrored = mock(
+        "GET",
+        format!("/v2/dockerhub/{}/blobs/{}", name, digest).as_str(),
+    )
+    .match_header("Authorization", Matcher::Missing)
+    .with_status(200)
+    .with_body(blob)
+    .expect(1)
+    .create();
+    let upstream = mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .expect(0)
+        .create();
+
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .bearer_token("upstream-token".to_string())
+        .mirror(mock_mirror("dockerhub"))
+        .build()?;
+
+    let runtime = Runtime::new().unwrap();
+    let result = runtime.block_on(dclient.get_blob(name, &digest))?;
+    assert_eq!(blob, result.as_slice());
+
+    mirrored.assert();
+    upstream.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn mirrors_fall_back_in_order() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+    let name = "library/busybox";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mirror_ep = |mirror: &str| format!("/v2/{}/{}/blobs/{}", mirror, name, digest);
+
+    let missing = mock("GET", mirror_ep("first").as_str())
+        .with_status(404)
+        .expect(1)
+        .create();
+    // Pull-through caches may require their own credentials.
+    let unauthorized = mock("GET", mirror_ep("cache").as_str())
+        .with_status(401)
+        .with_header("WWW-Authenticate", "Basic realm=\"cache\"")
+        .expect(1)
+        .create();
+    let failing = mock("GET", mirror_ep("broken").as_str())
+        .with_status(503)
+        .expect(1)
+        .create();
+    let upstream = mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .with_status(200)
+        .with_body(blob)
+        .expect(1)
+        .create();
+
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .mirror(unreachable_mirror())
+        .mirror(mock_mirror("first"))
+        .mirror(mock_mirror("cache"))
+        .mirror(mock_mirror("broken"))
+        .build()?;
+
+    let runtime = Runtime::new().unwrap();
+    let result = runtime.block_on(dclient.get_blob(name, &digest))?;
+    assert_eq!(blob, result.as_slice());
+
+    missing.assert();
+    unauthorized.assert();
+    failing.assert();
+    upstream.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn mirrors_ignoring_ranges_are_skipped() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+    let name = "library/busybox";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let target_dir = std::env::temp_dir().join("dkregistry-test-mirror-range");
+    let _ = std::fs::remove_dir_all(&target_dir);
+    std::fs::create_dir_all(&target_dir)?;
+    std::fs::write(target_dir.join(format!("{}.partial", digest)), b"hel")?;
+
+    let mirrored = mock(
+        "GET",
+        format!("/v2/dockerhub/{}/blobs/{}", name, digest).as_str(),
+    )
+    .match_header("Range", "bytes=3-")
+    .with_status(200)
+    .with_body(blob)
+    .expect(1)
+    .create();
+    let upstream = mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .match_header("Range", "bytes=3-")
+        .with_status(206)
+        .with_header("Content-Range", "bytes 3-4/5")
+        .with_body(b"lo")
+        .expect(1)
+        .create();
+
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .mirror(mock_mirror("dockerhub"))
+        .build()?;
+
+    let runtime = Runtime::new().unwrap();
+    let path =
+        runtime.block_on(dclient.get_blob_with_progress_file(name, &digest, None, &target_dir))?;
+    assert_eq!(blob, std::fs::read(&path)?.as_slice());
+
+    mirrored.assert();
+    upstream.assert();
+
+    std::fs::remove_dir_all(&target_dir)?;
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn existence_checks_are_not_mirrored() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+    let name = "library/busybox";
+    let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+    let mirrored = mock("HEAD", Matcher::Regex("^/v2/dockerhub/".to_string()))
+        .with_status(200)
+        .expect(0)
+        .create();
+    let upstream = mock("HEAD", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .with_status(404)
+        .expect(1)
+        .create();
+
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .mirror(mock_mirror("dockerhub"))
+        .build()?;
+
+    let runtime = Runtime::new().unwrap();
+    assert!(!runtime.block_on(dclient.has_blob(name, digest))?);
+
+    mirrored.assert();
+    upstream.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn pushes_are_not_mirrored() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+    let name = "library/busybox";
+
+    let mirrored = mock("POST", Matcher::Regex("^/v2/dockerhub/".to_string()))
+        .expect(0)
+        .create();
+    let upstream = mock("POST", format!("/v2/{}/blobs/uploads/", name).as_str())
+        .with_status(202)
+        .with_header("Location", &format!("/v2/{}/blobs/uploads/1234", name))
+        .with_header("Range", "0-0")
+        .expect(1)
+        .create();
+
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .mirror(mock_mirror("dockerhub"))
+        .build()?;
+
+    let runtime = Runtime::new().unwrap();
+    runtime.block_on(dclient.start_blob_upload(name))?;
+
+    mirrored.assert();
+    upstream.assert();
+
+    mockito::reset();
+    Ok(())
+}
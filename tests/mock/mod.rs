@@ -1,5 +1,11 @@
 mod api_version;
+mod auth_token;
 mod base_client;
+mod blobs_delete;
 mod blobs_download;
+mod blobs_redirect;
+mod blobs_upload;
 mod catalog;
+mod manifest;
+mod mirrors;
 mod tags;
@@ -145,3 +145,33 @@ fn test_tags_missing_header() {
 
     mockito::reset();
 }
+
+#[test]
+fn test_tags_name_unknown() {
+    let name = "repo";
+    let body = r#"{"errors": [{"code": "NAME_UNKNOWN", "message": "repository name not known to registry", "detail": {"name": "repo"}}]}"#;
+    let ep = format!("/v2/{}/tags/list", name);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .with_status(404)
+        .with_header("Content-Type", "application/json")
+        .with_body(body)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_tags(name, None);
+
+    let res = runtime.block_on(futcheck.collect::<Vec<_>>());
+    let err = res.first().unwrap().as_ref().unwrap_err();
+    assert!(err.has_error_code(&dkregistry::v2::ApiErrorCode::NameUnknown));
+
+    mockito::reset();
+}